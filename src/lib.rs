@@ -1,7 +1,8 @@
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::{
-    parse_macro_input, Data, DeriveInput, Fields, GenericArgument, Lit, PathArguments, Type,
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Fields, GenericArgument, Lit,
+    PathArguments, Type,
 };
 
 #[proc_macro_derive(Model, attributes(model))]
@@ -17,23 +18,260 @@ pub fn model_derive(input: TokenStream) -> TokenStream {
         _ => panic!("Model derive macro only supports structs"),
     };
 
+    let mut soft_delete = None;
+    let mut uppercase_keywords = false;
+    let mut from_row = false;
+    let mut upsert_on = None;
+    let mut require_default_for_not_null = false;
+    // `default_raw` is only checked with the `strict-defaults` feature or
+    // once a model extends the list
+    let mut allowed_functions = cfg!(feature = "strict-defaults").then(|| {
+        DEFAULT_FUNCTIONS
+            .iter()
+            .map(|function| function.to_string())
+            .collect::<Vec<_>>()
+    });
+    let mut table_comment = None;
+    let mut lowercase_identifiers = false;
+    let mut relations = Vec::new();
+    let mut cascades = Vec::new();
+    let (mut relation, mut fk) = (None, None);
+
+    for attr in &input.attrs {
+        if attr.path.is_ident("model") {
+            let meta = attr.parse_meta().unwrap();
+            if let syn::Meta::List(ref list) = meta {
+                for nested in &list.nested {
+                    let nv = match model_attribute(nested, STRUCT_ATTRIBUTES) {
+                        Ok(nv) => nv,
+                        Err(err) => return err.to_compile_error().into(),
+                    };
+                    if nv.path.is_ident("soft_delete") {
+                        if let Lit::Str(ref lit) = nv.lit {
+                            soft_delete = Some(lit.clone());
+                        }
+                    } else if nv.path.is_ident("comment") {
+                        if let Lit::Str(ref lit) = nv.lit {
+                            table_comment = Some(lit.value());
+                        }
+                    } else if nv.path.is_ident("uppercase_keywords") {
+                        // only reaches the statements generated here, the ones
+                        // the runtime builds such as `Model::create` and
+                        // `Model::set` stay in lowercase
+                        if let Lit::Bool(ref lit) = nv.lit {
+                            uppercase_keywords = lit.value;
+                        }
+                    } else if nv.path.is_ident("allowed_functions") {
+                        if let Lit::Str(ref lit) = nv.lit {
+                            let functions = allowed_functions.get_or_insert_with(|| {
+                                DEFAULT_FUNCTIONS
+                                    .iter()
+                                    .map(|function| function.to_string())
+                                    .collect()
+                            });
+                            functions.extend(
+                                lit.value()
+                                    .split(',')
+                                    .map(|function| function.trim().to_lowercase())
+                                    .filter(|function| !function.is_empty()),
+                            );
+                        }
+                    } else if nv.path.is_ident("require_default_for_not_null") {
+                        if let Lit::Bool(ref lit) = nv.lit {
+                            require_default_for_not_null = lit.value;
+                        }
+                    } else if nv.path.is_ident("upsert_on") {
+                        if let Lit::Str(ref lit) = nv.lit {
+                            upsert_on = Some(lit.clone());
+                        }
+                    } else if nv.path.is_ident("from_row") {
+                        if let Lit::Bool(ref lit) = nv.lit {
+                            from_row = lit.value;
+                        }
+                    } else if nv.path.is_ident("identifier_case") {
+                        match nv.lit {
+                            Lit::Str(ref lit) if lit.value() == "lower" => {
+                                lowercase_identifiers = true;
+                            }
+                            Lit::Str(ref lit) if lit.value() == "preserve" => {
+                                lowercase_identifiers = false;
+                            }
+                            ref lit => {
+                                return syn::Error::new_spanned(
+                                    lit,
+                                    "`identifier_case` must be \"lower\" or \"preserve\"",
+                                )
+                                .to_compile_error()
+                                .into();
+                            }
+                        }
+                    } else if nv.path.is_ident("cascade") {
+                        if let Lit::Str(ref lit) = nv.lit {
+                            match lit.value().split_once('.') {
+                                Some((child, column))
+                                    if !child.is_empty()
+                                        && !column.is_empty()
+                                        && !column.contains('.') =>
+                                {
+                                    cascades.push((
+                                        child.to_string(),
+                                        column.to_string(),
+                                        lit.clone(),
+                                    ));
+                                }
+                                _ => {
+                                    return syn::Error::new_spanned(
+                                        lit,
+                                        "`cascade` must be of the form \"child_table.column\"",
+                                    )
+                                    .to_compile_error()
+                                    .into();
+                                }
+                            }
+                        }
+                    } else if nv.path.is_ident("relation") {
+                        if let Lit::Str(ref lit) = nv.lit {
+                            relation = Some(lit.clone());
+                        }
+                    } else if nv.path.is_ident("fk") {
+                        if let Lit::Str(ref lit) = nv.lit {
+                            fk = Some(lit.clone());
+                        }
+                    }
+                }
+                match (relation.take(), fk.take()) {
+                    (Some(relation), Some(fk)) => {
+                        let Ok(parent) = relation.parse::<syn::Path>() else {
+                            return syn::Error::new_spanned(relation, "expected a model path")
+                                .to_compile_error()
+                                .into();
+                        };
+                        if !fields
+                            .iter()
+                            .any(|f| f.ident.as_ref().unwrap() == &fk.value())
+                        {
+                            return syn::Error::new_spanned(
+                                fk,
+                                "`fk` must name a field of this model",
+                            )
+                            .to_compile_error()
+                            .into();
+                        }
+                        relations.push((parent, fk));
+                    }
+                    (None, None) => {}
+                    _ => {
+                        return syn::Error::new_spanned(
+                            list,
+                            "`relation` requires `fk` and vice versa",
+                        )
+                        .to_compile_error()
+                        .into();
+                    }
+                }
+            }
+        }
+    }
+
+    let table = identifier(&name.to_string(), lowercase_identifiers, uppercase_keywords);
+
+    // identifiers that double as keywords are quoted, so they keep their casing
+    let keywords = |query: String| {
+        if uppercase_keywords {
+            to_uppercase_keywords(&query)
+        } else {
+            query
+        }
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let generic_params = input
+        .generics
+        .type_params()
+        .map(|param| param.ident.to_string())
+        .collect::<Vec<_>>();
+
     let mut schema_fields = Vec::new();
+    let mut alter_fields = Vec::new();
+    let mut test_schema_fields = Vec::new();
+    // foreign keys declared after the columns, mysql ignores inline ones
+    let mut foreign_key_constraints = Vec::new();
+    let mut column_types = Vec::new();
+    let mut columns = Vec::new();
+    let mut column_comments = Vec::new();
+    let mut numeric_columns = Vec::new();
+    let mut float_columns = Vec::new();
+    let mut related_loaders = Vec::new();
+    let mut row_fields = Vec::new();
+    let mut nullable_columns = Vec::new();
+    let mut cdc_columns = Vec::new();
+    let mut column_sql_types = Vec::new();
     let mut create_args = Vec::new();
+    let mut create_fns = Vec::new();
     let mut update_args = Vec::new();
+    let mut natural_key = Vec::new();
+    let mut unique_key = Vec::new();
 
-    let mut the_primary_key = quote! {};
+    let mut primary_keys: Vec<(&syn::Ident, &Type)> = Vec::new();
+    let is_composite_key = fields
+        .iter()
+        .filter(|field| is_primary_key_field(field))
+        .count()
+        > 1;
+    // a single child column can't reference a key spanning several columns
+    if let Some((_, _, lit)) = cascades.first().filter(|_| is_composite_key) {
+        return syn::Error::new_spanned(
+            lit,
+            "`cascade` isn't supported on models with a composite primary key",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let mut the_primary_key_type = String::new();
+    let mut has_generated_key = false;
+    let mut relation_checks = Vec::new();
+    let mut has_deferrable = false;
+    let mut depends_on = Vec::new();
+    let mut enum_statements = Vec::new();
+    let mut foreign_key_names = Vec::new();
 
     for field in fields {
         let field_name = field.ident.as_ref().unwrap();
         let field_type = extract_inner_type(&field.ty);
+        let column = identifier(
+            &field_name.to_string(),
+            lowercase_identifiers,
+            uppercase_keywords,
+        );
 
         let mut is_primary_key = false;
         let mut is_auto = false;
         let mut is_unique = false;
         let mut is_default = false;
+        let mut is_default_raw = false;
         let mut size = None;
+        let mut is_fixed = false;
+        let mut min_size = None;
+        let mut column_type = None;
         let mut default = quote! {};
         let mut foreign_key = quote! {};
+        let mut is_deferrable = false;
+        let mut default_dialect = None;
+        let mut comment = None;
+        let mut default_literal = None;
+        let mut bool_default = None;
+        let mut dialect_types = Vec::new();
+        let mut enum_table = None;
+        let mut default_fn = None;
+        let mut default_from = None;
+        let mut is_immutable = false;
+        let mut is_auto_now_add = false;
+        let mut is_cdc = false;
+        let mut on_update = None;
+        let mut check_expr = None;
+        let mut choices = None;
+        let mut foreign_key_target = None;
+        let mut related = None;
 
         let is_nullable = match &field.ty {
             syn::Type::Path(type_path) => {
@@ -51,109 +289,745 @@ pub fn model_derive(input: TokenStream) -> TokenStream {
                 let meta = attr.parse_meta().unwrap();
                 if let syn::Meta::List(ref list) = meta {
                     for nested in &list.nested {
-                        if let syn::NestedMeta::Meta(syn::Meta::NameValue(ref nv)) = nested {
-                            if nv.path.is_ident("primary_key") {
-                                if let Lit::Bool(ref lit) = nv.lit {
-                                    the_primary_key = quote! { #field_name.clone() };
-                                    is_primary_key = lit.value;
+                        let nv = &match model_attribute(nested, FIELD_ATTRIBUTES) {
+                            Ok(nv) => nv,
+                            Err(err) => return err.to_compile_error().into(),
+                        };
+                        if nv.path.is_ident("primary_key") {
+                            if let Lit::Bool(ref lit) = nv.lit {
+                                if lit.value && !is_primary_key {
+                                    primary_keys.push((field_name, &field.ty));
+                                } else if !lit.value && is_primary_key {
+                                    primary_keys.retain(|(ident, _)| *ident != field_name);
+                                }
+                                is_primary_key = lit.value;
+                            }
+                        } else if nv.path.is_ident("auto") {
+                            if let Lit::Bool(ref lit) = nv.lit {
+                                is_auto = lit.value;
+                            }
+                        } else if nv.path.is_ident("column_type") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                column_type = Some(lit.clone());
+                            }
+                        } else if let Some(dialect) = nv.path.get_ident().and_then(|ident| {
+                            ident.to_string().strip_prefix("db_type_").map(String::from)
+                        }) {
+                            let Some(dialect) = Dialect::from_name(&dialect) else {
+                                return syn::Error::new_spanned(
+                                    &nv.path,
+                                    "expected `db_type_sqlite`, `db_type_postgres` or `db_type_mysql`",
+                                )
+                                .to_compile_error()
+                                .into();
+                            };
+                            if let Lit::Str(ref lit) = nv.lit {
+                                dialect_types.push((dialect, lit.clone()));
+                            }
+                        } else if nv.path.is_ident("fixed") {
+                            if let Lit::Bool(ref lit) = nv.lit {
+                                is_fixed = lit.value;
+                            }
+                        } else if nv.path.is_ident("min_size") {
+                            if let Lit::Int(ref lit) = nv.lit {
+                                min_size = Some(lit.base10_parse::<u64>().unwrap());
+                            }
+                        } else if nv.path.is_ident("size") {
+                            if let Lit::Int(ref lit) = nv.lit {
+                                size = Some(lit.clone());
+                            }
+                        } else if nv.path.is_ident("unique") {
+                            if let Lit::Bool(ref lit) = nv.lit {
+                                is_unique = lit.value;
+                            }
+                        } else if nv.path.is_ident("default") {
+                            if is_default_raw {
+                                return syn::Error::new_spanned(
+                                    nv,
+                                    "`default` and `default_raw` are mutually exclusive",
+                                )
+                                .to_compile_error()
+                                .into();
+                            }
+                            is_default = true;
+                            if let Lit::Str(ref str) = nv.lit {
+                                default = if field_type == "Bytes" {
+                                    let Some(bytes) = binary_literal(&str.value()) else {
+                                        return syn::Error::new_spanned(
+                                            str,
+                                            "expected a hex literal such as \"0xDEADBEEF\"",
+                                        )
+                                        .to_compile_error()
+                                        .into();
+                                    };
+                                    quote! { default #bytes }
+                                } else if str.value() == "now" {
+                                    if field_type == "Date" {
+                                        quote! { default current_date }
+                                    } else if field_type == "DateTime" {
+                                        quote! { default current_timestamp }
+                                    } else if field_type == "Time" {
+                                        quote! { default current_time }
+                                    } else {
+                                        return syn::Error::new_spanned(
+                                            str,
+                                            "`now` is only supported on `Date`, `DateTime` and `Time` fields",
+                                        )
+                                        .to_compile_error()
+                                        .into();
+                                    }
+                                } else if ["Date", "DateTime", "Time"]
+                                    .contains(&field_type.as_str())
+                                    && is_now_expr(&str.value())
+                                {
+                                    let Some(expr) = default_expr(&str.value(), &field_type) else {
+                                        return syn::Error::new_spanned(
+                                            str,
+                                            "expected a default such as \"now + interval '7 days'\"",
+                                        )
+                                        .to_compile_error()
+                                        .into();
+                                    };
+                                    quote! { default #expr }
+                                } else {
+                                    if field_type == "Json" && !is_json(&str.value()) {
+                                        return syn::Error::new_spanned(
+                                            str,
+                                            "the default of a `Json` field must be valid JSON without duplicate keys",
+                                        )
+                                        .to_compile_error()
+                                        .into();
+                                    }
+                                    let str = format!("'{}'", str.value().replace('\'', "''"));
+                                    default_literal = Some(str.clone());
+                                    quote! { default #str }
                                 }
-                            } else if nv.path.is_ident("auto") {
-                                if let Lit::Bool(ref lit) = nv.lit {
-                                    is_auto = lit.value;
+                            } else if let Lit::Bool(ref bool) = nv.lit {
+                                // rendered once the column type is known
+                                bool_default = Some(bool.value);
+                            } else if let Lit::Int(ref int) = nv.lit {
+                                // drops `_` separators and type suffixes such as `1_000u32`
+                                let int = int.base10_digits();
+                                default = quote! { default #int }
+                            } else if let Lit::Float(ref float) = nv.lit {
+                                let float = float.base10_digits();
+                                default = quote! { default #float }
+                            } else {
+                                return syn::Error::new_spanned(
+                                    &nv.lit,
+                                    "a `default` must be a string, integer, float or boolean literal",
+                                )
+                                .to_compile_error()
+                                .into();
+                            }
+                        } else if nv.path.is_ident("default_raw") {
+                            if is_default && !is_default_raw {
+                                return syn::Error::new_spanned(
+                                    nv,
+                                    "`default` and `default_raw` are mutually exclusive",
+                                )
+                                .to_compile_error()
+                                .into();
+                            }
+                            if let Lit::Str(ref lit) = nv.lit {
+                                is_default = true;
+                                is_default_raw = true;
+                                let raw = lit.value();
+                                if let Some(ref allowed_functions) = allowed_functions {
+                                    if let Err(message) = check_default_raw(&raw, allowed_functions)
+                                    {
+                                        return syn::Error::new_spanned(lit, message)
+                                            .to_compile_error()
+                                            .into();
+                                    }
                                 }
-                            } else if nv.path.is_ident("size") {
-                                if let Lit::Int(ref lit) = nv.lit {
-                                    size = Some(lit.clone());
+                                default = quote! { default #raw };
+                            }
+                        } else if nv.path.is_ident("comment") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                comment = Some(lit.value());
+                            }
+                        } else if nv.path.is_ident("dialect") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                let Some(dialect) = Dialect::from_name(&lit.value()) else {
+                                    return syn::Error::new_spanned(
+                                        lit,
+                                        "expected \"sqlite\", \"postgres\" or \"mysql\"",
+                                    )
+                                    .to_compile_error()
+                                    .into();
+                                };
+                                default_dialect = Some(dialect);
+                            }
+                        } else if nv.path.is_ident("deferrable") {
+                            if let Lit::Bool(ref lit) = nv.lit {
+                                is_deferrable = lit.value;
+                            }
+                        } else if nv.path.is_ident("check") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                if let Err(message) = check_expression(&lit.value()) {
+                                    return syn::Error::new_spanned(lit, message)
+                                        .to_compile_error()
+                                        .into();
                                 }
-                            } else if nv.path.is_ident("unique") {
-                                if let Lit::Bool(ref lit) = nv.lit {
-                                    is_unique = lit.value;
+                                check_expr = Some(lit.value());
+                            }
+                        } else if nv.path.is_ident("on_update") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                let action = lit.value().to_lowercase();
+                                let actions = [
+                                    "cascade",
+                                    "restrict",
+                                    "set null",
+                                    "set default",
+                                    "no action",
+                                ];
+                                if !actions.contains(&action.as_str()) {
+                                    return syn::Error::new_spanned(
+                                        lit,
+                                        "expected \"cascade\", \"restrict\", \"set null\", \"set default\" or \"no action\"",
+                                    )
+                                    .to_compile_error()
+                                    .into();
                                 }
-                            } else if nv.path.is_ident("default") {
-                                is_default = true;
-                                if let Lit::Str(ref str) = nv.lit {
-                                    default = if str.value() == "now" {
-                                        if field_type == "Date" {
-                                            quote! { default current_date }
-                                        } else if field_type == "DateTime" {
-                                            quote! { default current_timestamp }
-                                        } else {
-                                            panic!("'now' is work only with Date or DateTime");
-                                        }
-                                    } else {
-                                        let str = format!("'{str}'", str = str.value());
-                                        quote! { default #str }
+                                on_update = Some(action);
+                            }
+                        } else if nv.path.is_ident("cdc") {
+                            if let Lit::Bool(ref lit) = nv.lit {
+                                is_cdc = lit.value;
+                            }
+                        } else if nv.path.is_ident("auto_now_add") {
+                            if let Lit::Bool(ref lit) = nv.lit {
+                                is_auto_now_add = lit.value;
+                            }
+                        } else if nv.path.is_ident("immutable") {
+                            if let Lit::Bool(ref lit) = nv.lit {
+                                is_immutable = lit.value;
+                            }
+                        } else if nv.path.is_ident("default_fn") {
+                            // called on insert, `apply_defaults` keeps the value
+                            if let Lit::Str(ref lit) = nv.lit {
+                                match lit.parse::<syn::Path>() {
+                                    Ok(path) => default_fn = Some(path),
+                                    Err(_) => {
+                                        return syn::Error::new_spanned(
+                                            lit,
+                                            "`default_fn` must be a function path such as \"Uuid::new_v4\"",
+                                        )
+                                        .to_compile_error()
+                                        .into();
                                     }
-                                } else if let Lit::Bool(ref bool) = nv.lit {
-                                    default = if bool.value {
-                                        quote! { default 1 }
-                                    } else {
-                                        quote! { default 0 }
-                                    };
-                                } else if let Lit::Int(ref int) = nv.lit {
-                                    default = quote! { default #int }
                                 }
-                            } else if nv.path.is_ident("foreign_key") {
-                                if let Lit::Str(ref lit) = nv.lit {
-                                    let fk = lit.value();
-                                    let foreign_key_parts: Vec<&str> = fk.split('.').collect();
-                                    if foreign_key_parts.len() != 2 {
-                                        panic!("Invalid foreign key");
+                            }
+                        } else if nv.path.is_ident("default_from") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                let source = fields
+                                    .iter()
+                                    .filter_map(|field| field.ident.as_ref())
+                                    .find(|ident| *ident == &lit.value() && *ident != field_name);
+                                match source {
+                                    Some(source) => default_from = Some(source.clone()),
+                                    None => {
+                                        return syn::Error::new_spanned(
+                                            lit,
+                                            "`default_from` must name another field of the model",
+                                        )
+                                        .to_compile_error()
+                                        .into();
                                     }
-                                    let foreign_key_table = foreign_key_parts[0];
-                                    let foreign_key_field = foreign_key_parts[1];
-
-                                    foreign_key = quote! {
-                                        references #foreign_key_table(#foreign_key_field)
-                                    };
                                 }
                             }
+                        } else if nv.path.is_ident("enum_table") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                enum_table = Some(lit.clone());
+                            }
+                        } else if nv.path.is_ident("choices") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                choices = Some(lit.clone());
+                            }
+                        } else if nv.path.is_ident("foreign_key") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                let fk = lit.value();
+                                let foreign_key_parts: Vec<&str> = fk.split('.').collect();
+                                if foreign_key_parts.len() != 2
+                                    || foreign_key_parts.iter().any(|part| part.is_empty())
+                                {
+                                    return syn::Error::new_spanned(
+                                        lit,
+                                        "`foreign_key` must be of the form \"table.column\"",
+                                    )
+                                    .to_compile_error()
+                                    .into();
+                                }
+                                let foreign_key_table = identifier(
+                                    foreign_key_parts[0],
+                                    lowercase_identifiers,
+                                    uppercase_keywords,
+                                );
+                                let foreign_key_field = identifier(
+                                    foreign_key_parts[1],
+                                    lowercase_identifiers,
+                                    uppercase_keywords,
+                                );
+
+                                foreign_key = quote! {
+                                    references #foreign_key_table(#foreign_key_field)
+                                };
+                                depends_on.push(foreign_key_table.clone());
+                                foreign_key_target = Some((foreign_key_table, foreign_key_field));
+                            }
+                        } else if nv.path.is_ident("related") {
+                            if let Lit::Str(ref lit) = nv.lit {
+                                related = Some(lit.clone());
+                            }
                         }
                     }
                 }
             }
         }
 
+        if let Some(related) = related {
+            let Some((ref parent_table, ref parent_column)) = foreign_key_target else {
+                return syn::Error::new_spanned(related, "`related` requires a `foreign_key`")
+                    .to_compile_error()
+                    .into();
+            };
+            let parent = match related.parse::<syn::Path>() {
+                Ok(parent) => parent,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            let method = match field_name.to_string().strip_suffix("_id") {
+                Some(stem) if !stem.is_empty() => format_ident!("{stem}"),
+                _ => format_ident!(
+                    "{}",
+                    parent
+                        .segments
+                        .last()
+                        .unwrap()
+                        .ident
+                        .to_string()
+                        .to_lowercase()
+                ),
+            };
+            let query = keywords(format!(
+                "select {{}} from {parent_table} where {parent_column}=?1;"
+            ));
+            related_loaders.push(quote! {
+                /// Loads the row this one references through its foreign key.
+                pub async fn #method(&self, conn: &Connection) -> Result<Option<#parent>, sqlx::Error> {
+                    let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
+                    let query = format!(#query, #parent::COLUMNS)
+                        .replace("?", &placeholder)
+                        .replace("$", &placeholder);
+                    sqlx::query_as::<_, #parent>(&query)
+                        .bind(self.#field_name.clone())
+                        .fetch_optional(conn)
+                        .await
+                }
+            });
+        }
+
+        if default_dialect.is_some() && !is_default {
+            return syn::Error::new_spanned(field, "`dialect` requires a `default`")
+                .to_compile_error()
+                .into();
+        }
+        // a default for another dialect is dropped, the column is then
+        // provided on insert like any other
+        if default_dialect.is_some_and(|dialect| dialect != Dialect::current()) {
+            is_default = false;
+            default = quote! {};
+        }
+
+        if !dialect_types.is_empty() {
+            if column_type.is_some() {
+                return syn::Error::new_spanned(
+                    field,
+                    "`column_type` can't be combined with the `db_type_*` overrides",
+                )
+                .to_compile_error()
+                .into();
+            }
+            // other dialects fall back to the type of the field
+            column_type = dialect_types
+                .into_iter()
+                .find(|(dialect, _)| *dialect == Dialect::current())
+                .map(|(_, lit)| lit);
+        }
+
+        if let Some(value) = bool_default.filter(|_| is_default) {
+            let literal = bool_literal(value, column_type.is_none() && field_type == "Boolean");
+            default = quote! { default #literal };
+        }
+
+        let invalid = if is_auto_now_add
+            && (is_default || is_auto || default_fn.is_some() || default_from.is_some())
+        {
+            Some("`auto_now_add` can't be combined with `default`, `default_raw`, `auto`, `default_fn` or `default_from`")
+        } else if is_auto_now_add && !["Date", "DateTime", "Time"].contains(&field_type.as_str()) {
+            Some("`auto_now_add` is only supported on `Date`, `DateTime` and `Time` fields")
+        } else if default_fn.is_some() && (is_default || is_auto) {
+            Some("`default_fn` can't be combined with `default`, `default_raw` or `auto`")
+        } else if default_from.is_some() && (is_default || is_auto || default_fn.is_some()) {
+            Some("`default_from` can't be combined with `default`, `default_raw`, `auto` or `default_fn`")
+        } else if is_auto
+            && column_type.is_none()
+            && !["Integer", "BigInt", "Serial"].contains(&field_type.as_str())
+        {
+            Some("`auto` is only supported on `Integer`, `BigInt` and `Serial` fields")
+        } else if bool_default.is_some()
+            && column_type.is_none()
+            && field_type != "Boolean"
+            && field_type != "Integer"
+        {
+            Some("a boolean `default` is only supported on `Boolean` and `Integer` fields")
+        } else if size.is_some() && field_type != "String" {
+            Some("`size` is only supported on `String` fields")
+        } else if is_fixed && field_type != "String" {
+            Some("`fixed` is only supported on `String` fields")
+        } else if min_size.is_some() && field_type != "String" && field_type != "Text" {
+            Some("`min_size` is only supported on `String` and `Text` fields")
+        } else if min_size.is_some_and(|min_size| {
+            size.as_ref()
+                .is_some_and(|size| size.base10_parse::<u64>().unwrap() < min_size)
+        }) {
+            Some("`min_size` can't be larger than `size`")
+        } else if is_auto && is_composite_key {
+            Some("`auto` can't be used in a composite primary key")
+        } else if is_primary_key && is_default && (is_auto || field_type == "Serial") {
+            Some("auto-generated keys can't have a `default`, the database assigns them")
+        } else {
+            None
+        };
+        if let Some(message) = invalid {
+            return syn::Error::new_spanned(field, message)
+                .to_compile_error()
+                .into();
+        }
+
+        // the database stamps the row, left out of the insert like any other
+        // defaulted column; with `immutable` it's never written again
+        if is_auto_now_add {
+            is_default = true;
+            default = match field_type.as_str() {
+                "Date" => quote! { default current_date },
+                "Time" => quote! { default current_time },
+                _ => quote! { default current_timestamp },
+            };
+        }
+
+        match (enum_table, choices) {
+            (Some(enum_table), Some(choices)) => {
+                if !foreign_key.is_empty() {
+                    return syn::Error::new_spanned(
+                        enum_table,
+                        "`enum_table` can't be combined with `foreign_key`",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+                let values = choices
+                    .value()
+                    .split(',')
+                    .map(|choice| choice.trim().replace('\'', "''"))
+                    .collect::<Vec<_>>();
+                if values.iter().any(|value| value.is_empty()) {
+                    return syn::Error::new_spanned(
+                        choices,
+                        "`choices` must be a comma separated list of values",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+
+                let enum_table = identifier(
+                    &enum_table.value(),
+                    lowercase_identifiers,
+                    uppercase_keywords,
+                );
+                enum_statements.push(format!(
+                    "create table if not exists {enum_table} (value varchar(255) primary key);"
+                ));
+                for value in values {
+                    enum_statements.push(match Dialect::current() {
+                        Dialect::Sqlite | Dialect::Postgres => format!(
+                            "insert into {enum_table} (value) values ('{value}') on conflict do nothing;"
+                        ),
+                        Dialect::MySql => {
+                            format!("insert ignore into {enum_table} (value) values ('{value}');")
+                        }
+                    });
+                }
+                // `LOOKUP_TABLES` runs before `SCHEMA`, so the key is declared
+                // with the table like any other
+                foreign_key = quote! { references #enum_table(value) };
+                foreign_key_target = Some((enum_table, "value".to_string()));
+            }
+            (Some(enum_table), None) => {
+                return syn::Error::new_spanned(enum_table, "`enum_table` requires `choices`")
+                    .to_compile_error()
+                    .into();
+            }
+            (None, Some(choices)) => {
+                return syn::Error::new_spanned(choices, "`choices` requires `enum_table`")
+                    .to_compile_error()
+                    .into();
+            }
+            (None, None) => {}
+        }
+
+        let deferrable = if is_deferrable {
+            if foreign_key.is_empty() && !relations.iter().any(|(_, fk)| field_name == &fk.value())
+            {
+                return syn::Error::new_spanned(field, "`deferrable` requires a foreign key")
+                    .to_compile_error()
+                    .into();
+            }
+            has_deferrable = true;
+            // mysql always checks keys immediately and has no such clause
+            if Dialect::current() == Dialect::MySql {
+                quote! {}
+            } else {
+                quote! { deferrable initially immediate }
+            }
+        } else {
+            quote! {}
+        };
+
         let field_schema = {
-            let base_type = match field_type.as_str() {
-                "Serial" => quote! { serial },
-                "Integer" => quote! { integer },
-                "String" => {
-                    if let Some(size) = size {
-                        quote! { varchar(#size) }
-                    } else {
-                        quote! { varchar(255) }
+            // spliced in as text, not every SQL type is valid Rust tokens
+            let base_type = if let Some(ref column_type) = column_type {
+                let column_type = column_type.value();
+                quote! { #column_type }
+            } else if generic_params.contains(&field_type) {
+                return syn::Error::new_spanned(
+                    &field.ty,
+                    "generic fields require an explicit `column_type`",
+                )
+                .to_compile_error()
+                .into();
+            } else {
+                match field_type.as_str() {
+                    "Serial" => quote! { serial },
+                    "Integer" => quote! { integer },
+                    // sqlite integers are 64 bit already, and only a column
+                    // declared `integer` can be an autoincrement key
+                    "BigInt" => match Dialect::current() {
+                        Dialect::Sqlite => quote! { integer },
+                        Dialect::Postgres | Dialect::MySql => quote! { bigint },
+                    },
+                    "String" => {
+                        let size = size.map_or(quote! { 255 }, |size| quote! { #size });
+                        if is_fixed {
+                            quote! { char(#size) }
+                        } else {
+                            quote! { varchar(#size) }
+                        }
                     }
+                    "Float" => quote! { float },
+                    "Text" => quote! { text },
+                    "Date" => quote! { varchar(10) },
+                    "Boolean" => match Dialect::current() {
+                        Dialect::Sqlite => quote! { integer },
+                        Dialect::Postgres => quote! { boolean },
+                        Dialect::MySql => quote! { tinyint(1) },
+                    },
+                    "DateTime" => quote! { varchar(40) },
+                    "Time" => match Dialect::current() {
+                        Dialect::Sqlite => quote! { varchar(8) },
+                        Dialect::Postgres | Dialect::MySql => quote! { time },
+                    },
+                    // mysql can't index a `blob` without a prefix length
+                    "Bytes" => match Dialect::current() {
+                        Dialect::Postgres => quote! { bytea },
+                        Dialect::MySql if is_primary_key || is_unique => {
+                            quote! { varbinary(255) }
+                        }
+                        Dialect::Sqlite | Dialect::MySql => quote! { blob },
+                    },
+                    "Json" => match Dialect::current() {
+                        Dialect::Sqlite => quote! { text },
+                        Dialect::Postgres => quote! { jsonb },
+                        Dialect::MySql => quote! { json },
+                    },
+                    "Uuid" => match Dialect::current() {
+                        Dialect::Postgres => quote! { uuid },
+                        Dialect::Sqlite | Dialect::MySql => quote! { varchar(36) },
+                    },
+                    p_type => panic!(
+                        "Unexpected field type: '{}'. Expected one of: 'Serial', 'Integer', 'BigInt', 'String', 'Float', 'Text', 'Date', 'Boolean', 'DateTime', 'Time', 'Bytes', 'Json', 'Uuid'. Please check the field type.",
+                        p_type
+                    ),
                 }
-                "Float" => quote! { float },
-                "Text" => quote! { text },
-                "Date" => quote! { varchar(10) },
-                "Boolean" => quote! { integer },
-                "DateTime" => quote! { varchar(40) },
-                p_type => panic!(
-                    "Unexpected field type: '{}'. Expected one of: 'Serial', 'Integer', 'String', 'Float', 'Text', 'Date', 'Boolean', 'DateTime'. Please check the field type.",
-                    p_type
-                ),
             };
 
+            columns.push(column.clone());
+            if is_nullable {
+                nullable_columns.push(column.clone());
+            }
+            if is_cdc {
+                cdc_columns.push(column.clone());
+            }
+            let name = column.trim_matches(|c| c == '"' || c == '`');
+            // sqlite stores booleans as integers
+            row_fields.push(
+                match (
+                    column_type.is_none()
+                        && field_type == "Boolean"
+                        && Dialect::current() == Dialect::Sqlite,
+                    is_nullable,
+                ) {
+                    (true, false) => quote! { #field_name: row.try_get::<i32, _>(#name)? != 0 },
+                    (true, true) => quote! {
+                        #field_name: row.try_get::<Option<i32>, _>(#name)?.map(|value| value != 0)
+                    },
+                    (false, _) => quote! { #field_name: row.try_get(#name)? },
+                },
+            );
+            if column_type.is_none()
+                && ["Serial", "Integer", "BigInt", "Float"].contains(&field_type.as_str())
+            {
+                numeric_columns.push(column.clone());
+                if field_type == "Float" {
+                    float_columns.push(column.clone());
+                }
+            }
+            let base_type_sql = sql(&base_type.to_string());
+            column_types.push(format!("{column} {base_type_sql}"));
+
+            // a serial key is referenced as a plain integer column
+            let sql_type = if field_type.as_str() == "Serial" {
+                "integer".to_string()
+            } else {
+                base_type_sql.replace(" (", "(").replace(" [", "[")
+            };
+
+            column_sql_types.push((column.clone(), sql_type.clone()));
+
+            if let Some(ref literal) = default_literal {
+                let is_timestamptz =
+                    ["timestamptz", "timestamp with time zone"].contains(&sql_type.as_str());
+                if is_timestamptz && !is_timestamp(&literal[1..literal.len() - 1]) {
+                    return syn::Error::new_spanned(
+                        field,
+                        "expected a default such as \"2020-01-01T00:00:00+00:00\"",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+            }
+
+            // postgres won't coerce an untyped literal into these on its own
+            if let Some(ref literal) = default_literal {
+                if Dialect::current() == Dialect::Postgres && needs_explicit_cast(&sql_type) {
+                    let literal = format!("{literal}::{sql_type}");
+                    default = quote! { default #literal };
+                }
+            }
+
+            if let Some((parent, _)) = relations.iter().find(|(_, fk)| field_name == &fk.value()) {
+                let parent_name = parent.segments.last().unwrap().ident.to_string();
+                if foreign_key.is_empty() {
+                    let parent_table =
+                        identifier(&parent_name, lowercase_identifiers, uppercase_keywords);
+                    foreign_key = quote! { references #parent_table };
+                    depends_on.push(parent_table);
+                }
+                let message = format!(
+                    "`{field_name}` must have the same type as the primary key of `{parent_name}`"
+                );
+                relation_checks.push(quote! {
+                    const _: () = {
+                        let expected = #parent::PK_TYPE.as_bytes();
+                        let actual = #sql_type.as_bytes();
+                        let mut same = expected.len() == actual.len();
+                        let mut i = 0;
+                        while same && i < expected.len() {
+                            same = expected[i] == actual[i];
+                            i += 1;
+                        }
+                        assert!(same, #message);
+                    };
+                });
+            }
+
+            let inserted_before = create_args.len();
             let primary_key = if is_primary_key {
+                if primary_keys.len() == 1 {
+                    the_primary_key_type = sql_type;
+                }
+                // a lone key with a ddl default such as `gen_random_uuid()` is
+                // left to the database like an auto key
+                has_generated_key =
+                    is_auto || field_type == "Serial" || (is_default && !is_composite_key);
                 let auto = if is_auto {
-                    quote! { autoincrement }
-                } else if field_type.as_str() == "Serial" {
+                    match Dialect::current() {
+                        Dialect::Sqlite => quote! { autoincrement },
+                        Dialect::MySql => quote! { auto_increment },
+                        // a serial column comes with its own sequence
+                        Dialect::Postgres if field_type == "Serial" => quote! {},
+                        Dialect::Postgres => quote! { generated by default as identity },
+                    }
+                } else if has_generated_key {
                     quote! {}
                 } else {
+                    // a defaulted part of a composite key is inserted all the
+                    // same, `update` and `delete` address the row by its value
                     create_args.push(quote! { #field_name });
+                    create_fns.push((&field.ty, default_fn.clone(), default_from.clone()));
+                    // a generated value can't be looked up before it exists
+                    if default_fn.is_none() && default_from.is_none() {
+                        natural_key.push((field_name, column.clone(), is_nullable));
+                    }
                     quote! {}
                 };
-                quote! { primary key #auto }
+                // composite keys are declared once as a table constraint
+                if is_composite_key {
+                    quote! {}
+                } else {
+                    quote! { primary key #auto }
+                }
             } else {
-                create_args.push(quote! { #field_name });
-                update_args.push(quote! { #field_name });
+                if !is_default {
+                    create_args.push(quote! { #field_name });
+                    create_fns.push((&field.ty, default_fn.clone(), default_from.clone()));
+                    if default_fn.is_none() && default_from.is_none() {
+                        natural_key.push((field_name, column.clone(), is_nullable));
+                    }
+                }
+                if is_unique {
+                    unique_key.push((field_name, column.clone(), is_nullable));
+                }
+                // write-once columns are set on insert only, the soft delete
+                // stamp by `delete` and `restore` alone
+                let is_soft_delete = soft_delete
+                    .as_ref()
+                    .is_some_and(|column| field_name == &column.value());
+                if !is_immutable && !is_soft_delete {
+                    update_args.push(quote! { #field_name });
+                }
                 quote! {}
             };
 
-            if is_default {
-                create_args.pop();
+            // the database fills auto and serial keys itself, anything else
+            // left out of the insert relies on the ddl default, which a
+            // `default null` doesn't provide
+            let is_generated = is_auto || field_type == "Serial";
+            let has_default = sql(&default.to_string())
+                .strip_prefix("default ")
+                .is_some_and(|default| !default.trim().eq_ignore_ascii_case("null"));
+            if require_default_for_not_null
+                && !is_nullable
+                && !has_default
+                && !is_generated
+                && create_args.len() == inserted_before
+            {
+                return syn::Error::new_spanned(
+                    field,
+                    "this `not null` column is left out of inserts and needs a non-null `default`",
+                )
+                .to_compile_error()
+                .into();
             }
 
             let nullable = if is_nullable {
@@ -166,102 +1040,2499 @@ pub fn model_derive(input: TokenStream) -> TokenStream {
             } else {
                 quote! {}
             };
+            let check = if let Some(min_size) = min_size {
+                let length = if Dialect::current() == Dialect::MySql {
+                    quote! { char_length }
+                } else {
+                    quote! { length }
+                };
+                let min_size = proc_macro2::Literal::u64_unsuffixed(min_size);
+                quote! { check (#length(#column) >= #min_size) }
+            } else {
+                quote! {}
+            };
+            let check = match check_expr {
+                Some(ref expr) => quote! { #check check (#expr) },
+                None => check,
+            };
+            if let Some(ref action) = on_update {
+                if foreign_key.is_empty() {
+                    return syn::Error::new_spanned(field, "`on_update` requires a foreign key")
+                        .to_compile_error()
+                        .into();
+                }
+                foreign_key = quote! { #foreign_key on update #action };
+            }
+            // named after the column so two keys to the same table can't
+            // collide. mysql parses inline references but ignores them, its
+            // keys are table constraints, which spell out the parent column
+            if !foreign_key.is_empty() {
+                let constraint = foreign_key_name(&table, &column);
+                if foreign_key_names.contains(&constraint) {
+                    return syn::Error::new_spanned(
+                        field,
+                        format!("foreign key constraint `{constraint}` is declared twice"),
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+                match (Dialect::current(), &foreign_key_target) {
+                    (Dialect::MySql, Some((parent_table, parent_column))) => {
+                        let on_update = on_update
+                            .as_ref()
+                            .map(|action| format!(" on update {action}"))
+                            .unwrap_or_default();
+                        foreign_key_constraints.push(format!(
+                            "constraint {constraint} foreign key ({column}) references {parent_table} ({parent_column}){on_update}"
+                        ));
+                        foreign_key = quote! {};
+                    }
+                    (Dialect::MySql, None) if on_update.is_some() => {
+                        return syn::Error::new_spanned(
+                            field,
+                            "on mysql `on_update` needs a `foreign_key = \"table.column\"`",
+                        )
+                        .to_compile_error()
+                        .into();
+                    }
+                    (Dialect::MySql, None) => {}
+                    _ => foreign_key = quote! { constraint #constraint #foreign_key },
+                }
+                foreign_key_names.push(constraint);
+            }
+
+            // existing rows can't satisfy `not null` without a default, and
+            // neither keys nor unique constraints can be added to a live table
+            // sqlite only adds columns with a constant default, one such as
+            // `current_timestamp` is dropped and the column left nullable
+            if !is_primary_key {
+                let default_sql = sql(&default.to_string());
+                let is_constant = default_sql
+                    .strip_prefix("default ")
+                    .is_none_or(is_constant_default);
+                let (default, nullable) = if !is_default {
+                    (default.clone(), quote! {})
+                } else if Dialect::current() == Dialect::Sqlite && !is_constant {
+                    (quote! {}, quote! {})
+                } else {
+                    (default.clone(), nullable.clone())
+                };
+                alter_fields.push((
+                    column.clone(),
+                    quote! { #column #base_type #default #nullable #check #foreign_key },
+                ));
+            }
 
-            quote! { #field_name #base_type #primary_key #unique #default #nullable #foreign_key }
+            test_schema_fields
+                .push(quote! { #column #base_type #primary_key #unique #default #nullable #check });
+
+            quote! { #column #base_type #primary_key #unique #default #nullable #check #foreign_key #deferrable }
         };
 
         schema_fields.push(field_schema);
+
+        if let Some(comment) = comment {
+            column_comments.push((column.clone(), comment));
+        }
+    }
+
+    // update, delete and the key helpers all address a row by its key
+    if primary_keys.is_empty() {
+        return syn::Error::new_spanned(&name, "a model needs a `primary_key` field")
+            .to_compile_error()
+            .into();
+    }
+
+    let pk = primary_keys.first().map(|(ident, _)| *ident);
+    let pk_type = primary_keys.first().map(|(_, ty)| *ty);
+    let pk_name = pk
+        .map(|ident| {
+            identifier(
+                &ident.to_string(),
+                lowercase_identifiers,
+                uppercase_keywords,
+            )
+        })
+        .unwrap_or_default();
+
+    let pk_fields = primary_keys
+        .iter()
+        .map(|(ident, _)| *ident)
+        .collect::<Vec<_>>();
+    let pk_types = primary_keys.iter().map(|(_, ty)| *ty).collect::<Vec<_>>();
+    let pk_columns = pk_fields
+        .iter()
+        .map(|ident| {
+            identifier(
+                &ident.to_string(),
+                lowercase_identifiers,
+                uppercase_keywords,
+            )
+        })
+        .collect::<Vec<_>>();
+    let pk_binds = quote! { #(.bind::<#pk_types>(self.#pk_fields.clone()))* };
+    let pk_where = |first: usize| {
+        pk_columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| format!("{column}=?{}", first + index))
+            .collect::<Vec<_>>()
+            .join(" and ")
+    };
+
+    if is_composite_key {
+        let constraint = format!("primary key ({})", pk_columns.join(", "));
+        schema_fields.push(quote! { #constraint });
+        test_schema_fields.push(quote! { #constraint });
     }
+    schema_fields.extend(
+        foreign_key_constraints
+            .iter()
+            .map(|constraint| quote! { #constraint }),
+    );
+
+    let pk_predicate = {
+        let predicate = pk_columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| format!("{column} = ?{}", index + 1))
+            .collect::<Vec<_>>()
+            .join(" and ");
+        let predicate = placeholders(&keywords(predicate));
+        quote! {
+            /// The `where` fragment matching this row's primary key, for reuse
+            /// in hand-written queries, with `rusql_alchemy::PLACEHOLDER` in
+            /// place.
+            pub fn pk_predicate() -> &'static str {
+                #predicate
+            }
+        }
+    };
 
-    let primary_key = {
-        let pk = the_primary_key.to_string().replace(".clone()", "");
+    let change_pk = if is_composite_key {
+        quote! {}
+    } else {
+        let query = keywords(format!(
+            "update {table} set {pk_name}=?1 where {pk_name}=?2;"
+        ));
         quote! {
-            const PK: &'static str = #pk;
+            /// Moves the row to a new primary key. Children follow through
+            /// foreign keys declared with `on_update = "cascade"`.
+            pub async fn change_pk(&self, new_pk: #pk_type, conn: &Connection) -> Result<(), sqlx::Error> {
+                let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
+                sqlx::query(&#query.replace("?", &placeholder).replace("$", &placeholder))
+                    .bind(new_pk)
+                    .bind(self.#pk.clone())
+                    .execute(conn)
+                    .await?;
+                Ok(())
+            }
         }
     };
 
-    let schema = {
+    // an insert without columns, mysql has no `default values`
+    let default_values = match Dialect::current() {
+        Dialect::Sqlite | Dialect::Postgres => "default values",
+        Dialect::MySql => "() values ()",
+    };
+
+    let placeholder_style = quote! {
+        /// The bind parameter marker of the current dialect, `$` on postgres
+        /// where it's followed by the 1-based index, `?` elsewhere. This is
+        /// `rusql_alchemy::PLACEHOLDER`.
+        pub const fn placeholder_style() -> &'static str {
+            rusql_alchemy::PLACEHOLDER
+        }
+    };
+
+    // a composite key is represented by its first column
+    let primary_key = quote! {
+        const PK: &'static str = #pk_name;
+    };
+
+    let primary_key_type = quote! {
+        /// The SQL type of the primary key, the one a foreign key to this
+        /// model must have. Only the first column's on a composite key, like
+        /// `PK`, which names that column alone.
+        pub const PK_TYPE: &'static str = #the_primary_key_type;
+    };
+
+    let (schema, schema_version, schema_in) = {
         let fields = schema_fields
             .iter()
             .map(|f| f.to_string())
             .collect::<Vec<_>>()
             .join(", ");
 
-        let schema = format!("create table if not exists {name} ({fields});").replace('"', "");
+        let schema = sql(&format!(
+            "create table if not exists {} ({fields});",
+            quote! { #table }
+        ));
+        // keyword casing is left out, it doesn't change the table
+        let version = fnv1a(schema.as_bytes());
+        let schema = keywords(schema);
+        // keyword casing keeps the length, the table name starts right after
+        let (head, body) = schema.split_at("create table if not exists ".len());
+
+        (
+            quote! {
+                const SCHEMA: &'static str = #schema;
+            },
+            quote! {
+                /// Hash of the whole table definition, defaults and constraints
+                /// included. Compare it with the stored one to tell whether a
+                /// migration is due, it changes with the schema but two
+                /// versions say nothing about which one is newer.
+                pub const SCHEMA_VERSION: u64 = #version;
+            },
+            quote! {
+                /// `SCHEMA` with the table created in `schema`, for a schema
+                /// per tenant. Foreign keys still resolve through the search
+                /// path. `None` unless `schema` is a plain identifier.
+                pub fn create_table_sql_with_schema(schema: &str) -> Option<String> {
+                    let mut chars = schema.chars();
+                    let valid = schema.len() <= 63
+                        && chars
+                            .next()
+                            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+                    valid.then(|| format!("{}{schema}.{}", #head, #body))
+                }
+            },
+        )
+    };
 
+    let depends_on = {
+        let mut tables = Vec::new();
+        for parent_table in depends_on {
+            // a self reference doesn't need ordering
+            if parent_table != table && !tables.contains(&parent_table) {
+                tables.push(parent_table);
+            }
+        }
         quote! {
-            const SCHEMA: &'static str = #schema;
+            /// Tables referenced through foreign keys, their `SCHEMA` has to
+            /// be applied before this one.
+            pub const DEPENDS_ON: &'static [&'static str] = &[#(#tables),*];
         }
     };
 
-    let create = quote! {
-        async fn save(&self, conn: &Connection) -> bool {
-            Self::create(
-                kwargs!(
-                    #(#create_args = self.#create_args),*
-                ),
-                conn,
-            )
-            .await
+    let column_names = columns.clone();
+    let columns = columns.join(", ");
+    // callers may name a quoted column with or without its quotes
+    let column_pattern = |column: &String| {
+        let bare = column.trim_matches(|c| c == '"' || c == '`');
+        if bare == column {
+            quote! { #column }
+        } else {
+            quote! { #bare | #column }
         }
     };
+    let update_columns = update_args
+        .iter()
+        .map(|f| identifier(&f.to_string(), lowercase_identifiers, uppercase_keywords))
+        .collect::<Vec<_>>();
+    let columns_const = quote! {
+        /// Every column in struct declaration order, used by the generated
+        /// selects so positional row mapping doesn't depend on table layout.
+        pub const COLUMNS: &'static str = #columns;
 
-    let update = quote! {
-        async fn update(&self, conn: &Connection) -> bool {
-            Self::set(
-                self.#the_primary_key,
-                kwargs!(
-                    #(#update_args = self.#update_args),*
-                ),
-                conn,
-            )
-            .await
+        /// The columns of `Option` fields, the ones accepting `null`.
+        pub const NULLABLE_COLUMNS: &'static [&'static str] = &[#(#nullable_columns),*];
+
+        /// The columns `update` writes, keys and `immutable` fields excluded.
+        pub const UPDATE_COLUMNS: &'static [&'static str] = &[#(#update_columns),*];
+
+        /// The columns marked `cdc`, the payload of a change event.
+        pub const CDC_COLUMNS: &'static [&'static str] = &[#(#cdc_columns),*];
+    };
+
+    let columns_hash = {
+        let hash = fnv1a(column_types.join(", ").as_bytes());
+        quote! {
+            /// Hash of the column names and types, changes whenever the
+            /// persisted shape of the model does.
+            pub const COLUMNS_HASH: u64 = #hash;
         }
     };
 
-    let delete = {
-        let query =
-            format!("delete from {name} where {the_primary_key}=?1;").replace(".clone()", "");
+    let schema_for_test = if cfg!(feature = "test-schema") {
+        let fields = test_schema_fields
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let schema = keywords(sql(&format!(
+            "create temporary table if not exists {} ({fields});",
+            quote! { #table }
+        )));
+
         quote! {
-            async fn delete(&self, conn: &Connection) -> bool {
-                let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
-                sqlx::query(&#query.replace("?", &placeholder).replace("$", &placeholder))
-                    .bind(self.#the_primary_key)
-                    .execute(conn)
-                    .await
-                    .is_ok()
+            /// The table as a temporary table without foreign keys, so a
+            /// single model can be exercised in isolation.
+            pub fn schema_for_test() -> &'static str {
+                #schema
             }
         }
+    } else {
+        quote! {}
     };
 
-    let expanded = quote! {
-        #[async_trait]
-        impl Model for #name {
-            const NAME: &'static str = stringify!(#name);
-            #schema
-            #primary_key
-            #create
-            #update
-            #delete
+    let lookup_tables = enum_statements
+        .into_iter()
+        .map(keywords)
+        .collect::<Vec<_>>();
+    let mut migrations = Vec::new();
+
+    // sqlite has no comments, mysql would need them inline in the schema
+    if Dialect::current() == Dialect::Postgres {
+        if let Some(ref comment) = table_comment {
+            migrations.push(keywords(format!(
+                "comment on table {table} is '{}';",
+                comment.replace('\'', "''")
+            )));
         }
+        for (column, comment) in &column_comments {
+            migrations.push(keywords(format!(
+                "comment on column {table}.{column} is '{}';",
+                comment.replace('\'', "''")
+            )));
+        }
+    }
+
+    let alter_statements = {
+        let if_not_exists = if Dialect::current() == Dialect::Postgres {
+            "if not exists "
+        } else {
+            ""
+        };
+        alter_fields
+            .iter()
+            .map(|(column, f)| {
+                let alter = keywords(sql(&format!(
+                    "alter table {} add column {if_not_exists}{f};",
+                    quote! { #table }
+                )));
+                (column.clone(), alter)
+            })
+            .collect::<Vec<_>>()
     };
 
-    TokenStream::from(expanded)
-}
+    let alters = {
+        let alters = alter_statements.iter().map(|(_, alter)| alter);
 
-fn extract_inner_type(field_type: &Type) -> String {
-    match field_type {
-        Type::Path(type_path) => {
-            let last_segment = type_path.path.segments.last().unwrap();
-            if last_segment.ident == "Option" {
-                if let PathArguments::AngleBracketed(args) = &last_segment.arguments {
-                    if let Some(GenericArgument::Type(inner_type)) = args.args.first() {
-                        return extract_inner_type(inner_type);
-                    }
-                }
-            }
-            last_segment.ident.to_string()
+        quote! {
+            /// One `add column` statement per non-key column, for bringing an
+            /// existing table up to date. Only postgres skips columns that
+            /// already exist, elsewhere a statement fails with a duplicate
+            /// column error once it has run, so apply the ones `diff_schema`
+            /// plans rather than all of them. On sqlite a column whose default
+            /// isn't a constant, such as `now`, is added nullable and without
+            /// it.
+            pub const ALTERS: &'static [&'static str] = &[#(#alters),*];
         }
-        _ => panic!("Unsupported field type"),
+    };
+
+    let diff_schema = {
+        let known_columns = column_sql_types.iter().map(|(column, sql_type)| {
+            let name = column.trim_matches(|c| c == '"' || c == '`');
+            let alter = alter_statements
+                .iter()
+                .find(|(alter_column, _)| alter_column == column)
+                .map_or("", |(_, alter)| alter.as_str());
+            let retype = if Dialect::current() == Dialect::Postgres {
+                keywords(format!(
+                    "alter table {table} alter column {column} type {sql_type};"
+                ))
+            } else {
+                String::new()
+            };
+            quote! { (#name, #sql_type, #alter, #retype) }
+        });
+        let drop_column = keywords(format!("alter table {table} drop column {{}};"));
+        let reserved_words = RESERVED_WORDS;
+        let quoted = match Dialect::current() {
+            Dialect::Postgres => "\"{}\"",
+            Dialect::Sqlite | Dialect::MySql => "`{}`",
+        };
+        quote! {
+            /// Plans the statements that bring a live table, given as its
+            /// `create table` statement or column list, in line with `SCHEMA`.
+            /// Added and removed columns are handled, type changes only on
+            /// postgres. Types are compared in full once aliases such as
+            /// `int4`, `serial` or `character varying` are resolved.
+            pub fn diff_schema(live: &str) -> Vec<String> {
+                // (name, type, add column statement, change type statement)
+                let known_columns: &[(&str, &str, &str, &str)] = &[#(#known_columns),*];
+                let reserved_words: &[&str] = &[#(#reserved_words),*];
+                // the column type without its constraints, in a single spelling
+                fn normalize(sql_type: &str) -> String {
+                    let sql_type = sql_type.to_lowercase();
+                    let mut compact = String::new();
+                    for word in sql_type.split_whitespace() {
+                        if !compact.is_empty()
+                            && !compact.ends_with(['(', ','])
+                            && !word.starts_with(['(', ')', ','])
+                        {
+                            compact.push(' ');
+                        }
+                        compact.push_str(word);
+                    }
+                    let (base, size) = match compact.find('(') {
+                        Some(index) => compact.split_at(index),
+                        None => (compact.as_str(), ""),
+                    };
+                    let base = match base {
+                        "int" | "int4" | "serial" | "serial4" => "integer",
+                        "int8" | "bigserial" | "serial8" => "bigint",
+                        "int2" | "smallserial" | "serial2" => "smallint",
+                        "float" | "float8" => "double precision",
+                        "float4" => "real",
+                        "bool" => "boolean",
+                        "decimal" => "numeric",
+                        "character varying" => "varchar",
+                        "character" => "char",
+                        "timestamptz" => "timestamp with time zone",
+                        "timetz" => "time with time zone",
+                        base => base,
+                    };
+                    format!("{base}{size}")
+                }
+                let body = match (live.find('('), live.rfind(')')) {
+                    (Some(start), Some(end)) if start < end => &live[start + 1..end],
+                    _ => live,
+                };
+
+                let mut definitions = Vec::new();
+                let (mut depth, mut start) = (0, 0);
+                for (index, c) in body.char_indices() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        ',' if depth == 0 => {
+                            definitions.push(&body[start..index]);
+                            start = index + 1;
+                        }
+                        _ => {}
+                    }
+                }
+                definitions.push(&body[start..]);
+
+                let live_columns = definitions
+                    .iter()
+                    .filter_map(|definition| {
+                        let mut words = definition.split_whitespace();
+                        let name = words.next()?;
+                        let constraints = ["constraint", "primary", "foreign", "unique", "check"];
+                        if constraints.contains(&name.to_lowercase().as_str()) {
+                            return None;
+                        }
+                        let name = name.trim_matches(|c| c == '"' || c == '`' || c == '[' || c == ']');
+                        let constraints = [
+                            "primary", "not", "null", "default", "unique", "check", "references",
+                            "constraint", "collate", "generated", "autoincrement", "auto_increment",
+                        ];
+                        let sql_type = words
+                            .take_while(|word| !constraints.contains(&word.to_lowercase().as_str()))
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        Some((name.to_string(), normalize(&sql_type)))
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut statements = Vec::new();
+                for (name, sql_type, alter, retype) in known_columns {
+                    let live_column = live_columns
+                        .iter()
+                        .find(|(live_name, _)| live_name.eq_ignore_ascii_case(name));
+                    match live_column {
+                        None if !alter.is_empty() => statements.push(alter.to_string()),
+                        Some((_, live_type)) if !retype.is_empty() => {
+                            if *live_type != normalize(sql_type) {
+                                statements.push(retype.to_string());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                for (live_name, _) in &live_columns {
+                    if !known_columns.iter().any(|(name, ..)| live_name.eq_ignore_ascii_case(name)) {
+                        // quoted like `identifier()` would for one of our columns
+                        let column = if reserved_words.contains(&live_name.to_lowercase().as_str()) {
+                            format!(#quoted, live_name)
+                        } else {
+                            live_name.to_string()
+                        };
+                        statements.push(format!(#drop_column, column));
+                    }
+                }
+                statements
+            }
+        }
+    };
+
+    let migrations = quote! {
+        /// The `enum_table` lookup tables with their seed rows. Run them
+        /// before `SCHEMA`, which references them; every statement can be
+        /// run again.
+        pub const LOOKUP_TABLES: &'static [&'static str] = &[#(#lookup_tables),*];
+
+        /// Statements to run once `SCHEMA` has been applied, the postgres
+        /// comments.
+        pub const MIGRATIONS: &'static [&'static str] = &[#(#migrations),*];
+    };
+
+    // fields with a `default_fn` or `default_from` are filled in when they
+    // still hold their `Default::default()` value, the nil UUID for `Uuid`;
+    // ddl defaults can't read other columns so the copy happens here
+    let create_values = |receiver: proc_macro2::TokenStream| {
+        create_args
+            .iter()
+            .zip(&create_fns)
+            .map(|(field, client_default)| {
+                let (ty, default_fn, default_from) = client_default;
+                let fallback = match (default_fn, default_from) {
+                    (Some(default_fn), _) => quote! { #default_fn() },
+                    (None, Some(source)) => quote! { #receiver.#source.clone() },
+                    (None, None) => return quote! { #receiver.#field.clone() },
+                };
+                quote! {
+                    if #receiver.#field == <#ty as Default>::default() {
+                        #fallback
+                    } else {
+                        #receiver.#field.clone()
+                    }
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // `save` only borrows the row, so the values it fills in never reach
+    // `self`; `apply_defaults` writes them back ahead of it
+    let apply_defaults = {
+        let fills = create_args
+            .iter()
+            .zip(&create_fns)
+            .filter_map(|(field, (ty, default_fn, default_from))| {
+                let fallback = match (default_fn, default_from) {
+                    (Some(default_fn), _) => quote! { #default_fn() },
+                    (None, Some(source)) => quote! { self.#source.clone() },
+                    (None, None) => return None,
+                };
+                Some(quote! {
+                    if self.#field == <#ty as Default>::default() {
+                        self.#field = #fallback;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        if fills.is_empty() {
+            quote! {}
+        } else {
+            quote! {
+                /// Fills the `default_fn` and `default_from` fields still holding
+                /// their `Default::default()` value the way `save` would. `save`
+                /// doesn't write them back, so call this first when the row is
+                /// used afterwards: `update` and `delete` on an unfilled key look
+                /// for the nil value and match nothing.
+                pub fn apply_defaults(&mut self) {
+                    #(#fills)*
+                }
+            }
+        }
+    };
+
+    // built here rather than through `Model::create`, so reserved column
+    // names are quoted the same way as in every other statement
+    let (create, insert) = {
+        let insert_columns = create_args
+            .iter()
+            .map(|f| identifier(&f.to_string(), lowercase_identifiers, uppercase_keywords))
+            .collect::<Vec<_>>();
+        let conflict = match upsert_on {
+            Some(ref upsert_on) => {
+                let target = identifier(
+                    &upsert_on.value(),
+                    lowercase_identifiers,
+                    uppercase_keywords,
+                );
+                let is_key = (!is_composite_key && pk_columns.contains(&target))
+                    || unique_key.iter().any(|(_, column, _)| column == &target);
+                if !is_key {
+                    return syn::Error::new_spanned(
+                        upsert_on,
+                        "`upsert_on` must name a single column primary key or a unique field",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+
+                // columns left out of the insert keep their current value
+                let assignments = insert_columns
+                    .iter()
+                    .filter(|column| *column != &target && update_columns.contains(column))
+                    .map(|column| match Dialect::current() {
+                        Dialect::Sqlite | Dialect::Postgres => {
+                            format!("{column}=excluded.{column}")
+                        }
+                        Dialect::MySql => format!("{column}=values({column})"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match (Dialect::current(), assignments.is_empty()) {
+                    (Dialect::Sqlite | Dialect::Postgres, false) => {
+                        format!(" on conflict ({target}) do update set {assignments}")
+                    }
+                    (Dialect::Sqlite | Dialect::Postgres, true) => {
+                        format!(" on conflict ({target}) do nothing")
+                    }
+                    // mysql has no conflict target, any unique key triggers the update
+                    (Dialect::MySql, false) => format!(" on duplicate key update {assignments}"),
+                    (Dialect::MySql, true) => {
+                        format!(" on duplicate key update {target}={target}")
+                    }
+                }
+            }
+            None => String::new(),
+        };
+        let query = if insert_columns.is_empty() {
+            format!("insert into {table} {default_values}{conflict};")
+        } else {
+            let values = (1..=insert_columns.len())
+                .map(|index| format!("?{index}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "insert into {table} ({}) values ({values}){conflict};",
+                insert_columns.join(", ")
+            )
+        };
+        let query = placeholders(&keywords(query));
+        let create_values = create_values(quote! { self });
+        (
+            quote! {
+                async fn save(&self, conn: &Connection) -> bool {
+                    self.insert(conn).await.is_ok()
+                }
+            },
+            quote! {
+                async fn insert(&self, conn: &Connection) -> Result<(), sqlx::Error> {
+                    sqlx::query(#query)
+                        #(.bind(#create_values))*
+                        .execute(conn)
+                        .await?;
+                    Ok(())
+                }
+            },
+        )
+    };
+
+    // every key column takes part in the predicate, `Model::set` only
+    // knows about the first one
+    let update = if update_args.is_empty() {
+        quote! {
+            async fn update(&self, _conn: &Connection) -> bool {
+                true
+            }
+        }
+    } else {
+        let assignments = update_columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| format!("{column}=?{}", index + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let query = keywords(format!(
+            "update {table} set {assignments} where {};",
+            pk_where(update_args.len() + 1)
+        ));
+        let query = placeholders(&query);
+        quote! {
+            async fn update(&self, conn: &Connection) -> bool {
+                sqlx::query(#query)
+                    #(.bind(self.#update_args.clone()))*
+                    #pk_binds
+                    .execute(conn)
+                    .await
+                    .is_ok()
+            }
+        }
+    };
+
+    let delete_cascade = if cascades.is_empty() {
+        quote! {}
+    } else {
+        let queries = cascades
+            .iter()
+            .map(|(child, column, _)| {
+                keywords(format!(
+                    "delete from {child} where {column}=?1;",
+                    child = identifier(child, lowercase_identifiers, uppercase_keywords),
+                    column = identifier(column, lowercase_identifiers, uppercase_keywords),
+                ))
+            })
+            .collect::<Vec<_>>();
+        let query = keywords(format!("delete from {table} where {};", pk_where(1)));
+        quote! {
+            /// Deletes the rows of every `cascade` child table referencing this
+            /// row, then the row itself, for tables without `on delete cascade`.
+            /// All of it runs in one transaction. The rows are always removed,
+            /// `soft_delete` models included, and child tables don't get their
+            /// own cascades applied.
+            pub async fn delete_cascade(&self, conn: &Connection) -> Result<(), sqlx::Error> {
+                let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
+                let mut tx = conn.begin().await?;
+                for query in [#(#queries),*] {
+                    sqlx::query(&query.replace("?", &placeholder).replace("$", &placeholder))
+                        .bind::<#pk_type>(self.#pk.clone())
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                sqlx::query(&#query.replace("?", &placeholder).replace("$", &placeholder))
+                    #pk_binds
+                    .execute(&mut *tx)
+                    .await?;
+                tx.commit().await
+            }
+        }
+    };
+
+    let patch = if update_columns.is_empty() {
+        quote! {
+            /// Updates only the named columns, leaving the rest of the row as is.
+            pub async fn patch(&self, fields: &[&str], _conn: &Connection) -> Result<(), sqlx::Error> {
+                match fields.first() {
+                    Some(field) => Err(sqlx::Error::ColumnNotFound(field.to_string())),
+                    None => Ok(()),
+                }
+            }
+        }
+    } else {
+        let update = keywords("update {} set {assignments} where {predicate};".to_string());
+        let patterns = update_columns
+            .iter()
+            .map(column_pattern)
+            .collect::<Vec<_>>();
+        quote! {
+            /// Updates only the named columns, leaving the rest of the row as is.
+            pub async fn patch(&self, fields: &[&str], conn: &Connection) -> Result<(), sqlx::Error> {
+                let columns = fields
+                    .iter()
+                    .map(|field| match *field {
+                        #(#patterns => Ok(#update_columns),)*
+                        _ => Err(sqlx::Error::ColumnNotFound(field.to_string())),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if columns.is_empty() {
+                    return Ok(());
+                }
+
+                let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
+                let assignments = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(index, column)| format!("{column}=?{}", index + 1))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let predicate = [#(#pk_columns),*]
+                    .iter()
+                    .enumerate()
+                    .map(|(index, column)| format!("{column}=?{}", columns.len() + index + 1))
+                    .collect::<Vec<_>>()
+                    .join(" and ");
+                let query = format!(#update, #table)
+                .replace("?", &placeholder)
+                .replace("$", &placeholder);
+
+                let mut query = sqlx::query(&query);
+                for field in fields {
+                    query = match *field {
+                        #(#patterns => query.bind(self.#update_args.clone()),)*
+                        _ => unreachable!(),
+                    };
+                }
+                query #pk_binds.execute(conn).await?;
+                Ok(())
+            }
+        }
+    };
+
+    let (delete, hard_delete, restore, delete_sql) = {
+        // the placeholders are substituted here rather than on every call
+        let query = placeholders(&keywords(format!(
+            "delete from {table} where {};",
+            pk_where(1)
+        )));
+        let delete_sql = quote! {
+            /// The statement `delete` runs, `hard_delete` for soft deleted
+            /// models, with `rusql_alchemy::PLACEHOLDER` in place.
+            pub const DELETE_SQL: &'static str = #query;
+        };
+        let query = quote! { Self::DELETE_SQL };
+        let delete_query =
+            |vis: proc_macro2::TokenStream, method: syn::Ident, query: proc_macro2::TokenStream| {
+                quote! {
+                    #vis async fn #method(&self, conn: &Connection) -> bool {
+                        sqlx::query(#query)
+                            #pk_binds
+                            .execute(conn)
+                            .await
+                            .is_ok()
+                    }
+                }
+            };
+
+        match soft_delete {
+            Some(ref column) => {
+                let field = fields
+                    .iter()
+                    .find(|field| field.ident.as_ref().unwrap() == &column.value());
+                let now = match field.map(|field| (&field.ty, extract_inner_type(&field.ty))) {
+                    Some((Type::Path(type_path), field_type))
+                        if type_path.path.segments.last().unwrap().ident == "Option"
+                            && (field_type == "Date" || field_type == "DateTime") =>
+                    {
+                        if field_type == "Date" {
+                            "current_date"
+                        } else {
+                            "current_timestamp"
+                        }
+                    }
+                    _ => {
+                        return syn::Error::new_spanned(
+                            column,
+                            "`soft_delete` must name an `Option<Date>` or `Option<DateTime>` field",
+                        )
+                        .to_compile_error()
+                        .into();
+                    }
+                };
+
+                let soft_query = keywords(format!(
+                    "update {table} set {column} = {now} where {predicate};",
+                    column = identifier(&column.value(), lowercase_identifiers, uppercase_keywords),
+                    predicate = pk_where(1),
+                ));
+                let soft_query = placeholders(&soft_query);
+                let restore_query = keywords(format!(
+                    "update {table} set {column} = null where {predicate};",
+                    column = identifier(&column.value(), lowercase_identifiers, uppercase_keywords),
+                    predicate = pk_where(1),
+                ));
+                let restore_query = placeholders(&restore_query);
+                let soft = delete_query(quote! {}, format_ident!("delete"), quote! { #soft_query });
+                let hard = delete_query(quote! { pub }, format_ident!("hard_delete"), query);
+                let restore = delete_query(
+                    quote! { pub },
+                    format_ident!("restore"),
+                    quote! { #restore_query },
+                );
+                (soft, hard, restore, delete_sql)
+            }
+            None => (
+                delete_query(quote! {}, format_ident!("delete"), query),
+                quote! {},
+                quote! {},
+                delete_sql,
+            ),
+        }
+    };
+
+    let defer_constraints = if has_deferrable && Dialect::current() == Dialect::Postgres {
+        let query = keywords("set constraints all deferred;".to_string());
+        quote! {
+            /// Defers every deferrable constraint until the end of the current
+            /// transaction. Run it on the transaction before bulk operations.
+            pub async fn defer_constraints<'e, E>(executor: E) -> Result<(), sqlx::Error>
+            where
+                E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+            {
+                sqlx::query(#query)
+                    .execute(executor)
+                    .await
+                    .map(|_| ())
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let bulk_insert = |returning: Option<String>| {
+        let per_row = create_args.len();
+        let suffix = returning.clone().unwrap_or_default();
+        if per_row == 0 {
+            let query = keywords(format!("insert into {table} {default_values}{suffix};"));
+            let execute = if returning.is_some() {
+                quote! {
+                    let (id,) = sqlx::query_as::<_, (#pk_type,)>(#query).fetch_one(conn).await?;
+                    ids.push(id);
+                }
+            } else {
+                quote! { sqlx::query(#query).execute(conn).await?; }
+            };
+            quote! {
+                for _ in items {
+                    #execute
+                }
+            }
+        } else {
+            let columns = create_args
+                .iter()
+                .map(|f| identifier(&f.to_string(), lowercase_identifiers, uppercase_keywords))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let insert = keywords(format!("insert into {table} ({columns}) values "));
+            let create_values = create_values(quote! { item });
+            let (query, execute) = if returning.is_some() {
+                (
+                    quote! { sqlx::query_as::<_, (#pk_type,)>(&query) },
+                    quote! { ids.extend(query.fetch_all(conn).await?.into_iter().map(|(id,)| id)); },
+                )
+            } else {
+                (
+                    quote! { sqlx::query(&query) },
+                    quote! { query.execute(conn).await?; },
+                )
+            };
+            quote! {
+                // sqlite refuses statements with more than 999 bind variables
+                const MAX_VARIABLES: usize = 999;
+                let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
+                let rows_per_chunk = (MAX_VARIABLES / #per_row).max(1);
+                for chunk in items.chunks(rows_per_chunk) {
+                    let mut index = 0;
+                    let values = chunk
+                        .iter()
+                        .map(|_| {
+                            let row = (0..#per_row)
+                                .map(|_| {
+                                    index += 1;
+                                    format!("?{index}")
+                                })
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            format!("({row})")
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let query = format!("{}{}{};", #insert, values, #suffix)
+                        .replace("?", &placeholder)
+                        .replace("$", &placeholder);
+                    let mut query = #query;
+                    for item in chunk {
+                        query = query #(.bind(#create_values))*;
+                    }
+                    #execute
+                }
+            }
+        }
+    };
+
+    let bulk_create = {
+        let body = bulk_insert(None);
+        quote! {
+            pub async fn bulk_create(items: &[Self], conn: &Connection) -> Result<(), sqlx::Error> {
+                if items.is_empty() {
+                    return Ok(());
+                }
+                #body
+                Ok(())
+            }
+        }
+    };
+
+    // mysql has no `returning`, a composite key has no single value to return
+    let bulk_create_returning = if Dialect::current() == Dialect::MySql || is_composite_key {
+        quote! {}
+    } else {
+        let body = bulk_insert(Some(keywords(format!(" returning {}", pk_columns[0]))));
+        // a natural key is known before the insert, there's nothing to return
+        let save_returning_pk = if has_generated_key {
+            quote! {
+                /// Inserts the row in place of `save` and writes the primary key
+                /// the database generated back into `self`, so `update` and
+                /// `delete` reach the new row. Calling it after `save` inserts
+                /// the row twice.
+                pub async fn save_returning_pk(&mut self, conn: &Connection) -> Result<(), sqlx::Error> {
+                    let items = std::slice::from_ref(self);
+                    let mut ids = Vec::with_capacity(1);
+                    #body
+                    self.#pk = ids.pop().ok_or(sqlx::Error::RowNotFound)?;
+                    Ok(())
+                }
+            }
+        } else {
+            quote! {}
+        };
+        quote! {
+            #save_returning_pk
+
+            /// Inserts `items` like `bulk_create` and returns their primary keys.
+            /// Postgres returns them in insertion order, sqlite doesn't
+            /// guarantee any order for `returning` and needs 3.35 or later.
+            pub async fn bulk_create_returning(
+                items: &[Self],
+                conn: &Connection,
+            ) -> Result<Vec<#pk_type>, sqlx::Error> {
+                let mut ids = Vec::with_capacity(items.len());
+                #body
+                Ok(ids)
+            }
+        }
+    };
+
+    let count = {
+        let query = keywords(format!("select count(*) from {table};"));
+        quote! {
+            pub async fn count(conn: &Connection) -> Result<i64, sqlx::Error> {
+                let (count,) = sqlx::query_as::<_, (i64,)>(#query)
+                    .fetch_one(conn)
+                    .await?;
+                Ok(count)
+            }
+
+            pub async fn exists(conn: &Connection) -> Result<bool, sqlx::Error> {
+                Ok(Self::count(conn).await? > 0)
+            }
+        }
+    };
+
+    let all = {
+        let query = keywords(format!("select {columns} from {table};"));
+        quote! {
+            pub async fn all(conn: &Connection) -> Result<Vec<Self>, sqlx::Error> {
+                sqlx::query_as::<_, Self>(#query).fetch_all(conn).await
+            }
+        }
+    };
+
+    let find_one = {
+        let select = keywords(format!("select {columns} from {table}{{}} limit 1;"));
+        let filter = keywords(" where {}".to_string());
+        let and = keywords(" and ".to_string());
+        quote! {
+            /// The first row matching every `column = value` pair of `filter`.
+            pub async fn find_one(filter: Kwargs, conn: &Connection) -> Result<Option<Self>, sqlx::Error> {
+                let columns = filter
+                    .iter()
+                    .map(|(column, _)| Self::check_column(column))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let predicate = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(index, column)| format!("{column}=?{}", index + 1))
+                    .collect::<Vec<_>>()
+                    .join(#and);
+                let predicate = if predicate.is_empty() {
+                    predicate
+                } else {
+                    format!(#filter, predicate)
+                };
+                let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
+                let query = format!(#select, predicate)
+                    .replace("?", &placeholder)
+                    .replace("$", &placeholder);
+                let mut query = sqlx::query_as::<_, Self>(&query);
+                for (_, value) in filter {
+                    query = query.bind(value);
+                }
+                query.fetch_optional(conn).await
+            }
+        }
+    };
+
+    let database = match Dialect::current() {
+        Dialect::Sqlite => quote! { sqlx::Sqlite },
+        Dialect::Postgres => quote! { sqlx::Postgres },
+        Dialect::MySql => quote! { sqlx::MySql },
+    };
+
+    let find_by = {
+        // one static query per column, a borrowed `value` has to outlive it
+        let patterns = column_names.iter().map(column_pattern);
+        let queries = column_names.iter().map(|column| {
+            placeholders(&keywords(format!(
+                "select {columns} from {table} where {column}=?1;"
+            )))
+        });
+        quote! {
+            /// Every row whose `column` equals `value`, `column` has to be
+            /// one of `COLUMNS`.
+            pub async fn find_by<'q, V>(
+                column: &str,
+                value: V,
+                conn: &Connection,
+            ) -> Result<Vec<Self>, sqlx::Error>
+            where
+                V: 'q + Send + sqlx::Encode<'q, #database> + sqlx::Type<#database>,
+            {
+                let query: &'static str = match column {
+                    #(#patterns => #queries,)*
+                    _ => return Err(sqlx::Error::ColumnNotFound(column.to_string())),
+                };
+                sqlx::query_as::<_, Self>(query)
+                    .bind(value)
+                    .fetch_all(conn)
+                    .await
+            }
+        }
+    };
+
+    // a unique column identifies the row on its own, otherwise every value
+    // `save` inserts takes part in the lookup. With neither there is nothing
+    // to look a row up by, so the method isn't generated at all.
+    let lookup = if unique_key.is_empty() {
+        &natural_key
+    } else {
+        &unique_key
+    };
+    let get_or_create = if lookup.is_empty() {
+        quote! {}
+    } else {
+        let null_safe_eq = match Dialect::current() {
+            Dialect::Sqlite => "is",
+            Dialect::Postgres => "is not distinct from",
+            Dialect::MySql => "<=>",
+        };
+        let predicate = lookup
+            .iter()
+            .enumerate()
+            .map(|(index, (_, column, is_nullable))| {
+                let operator = if *is_nullable { null_safe_eq } else { "=" };
+                format!("{column} {operator} ?{}", index + 1)
+            })
+            .collect::<Vec<_>>()
+            .join(" and ");
+        let query = keywords(format!(
+            "select {columns} from {table} where {predicate} limit 1;"
+        ));
+        let lookup_fields = lookup.iter().map(|(field_name, _, _)| field_name);
+        quote! {
+            async fn lookup(&self, conn: &Connection) -> Result<Option<Self>, sqlx::Error> {
+                let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
+                let query = #query
+                    .replace("?", &placeholder)
+                    .replace("$", &placeholder);
+                sqlx::query_as::<_, Self>(&query)
+                    #(.bind(self.#lookup_fields.clone()))*
+                    .fetch_optional(conn)
+                    .await
+            }
+
+            /// The stored row matching this one on its unique columns, or on
+            /// every inserted column without any, inserting it first if there
+            /// is none. Models with nothing to match on don't get this method.
+            pub async fn get_or_create(&self, conn: &Connection) -> Result<Self, sqlx::Error> {
+                if let Some(row) = self.lookup(conn).await? {
+                    return Ok(row);
+                }
+                // when a concurrent insert wins the race the insert fails on
+                // the unique constraint and the lookup below finds that row
+                let inserted = self.insert(conn).await;
+                match self.lookup(conn).await? {
+                    Some(row) => Ok(row),
+                    None => Err(inserted.err().unwrap_or(sqlx::Error::RowNotFound)),
+                }
+            }
+        }
+    };
+
+    let count_by = {
+        let text = if Dialect::current() == Dialect::MySql {
+            "char"
+        } else {
+            "text"
+        };
+        let count_by = keywords(format!(
+            "select cast({{column}} as {text}), count(*) from {table} group by {{column}};"
+        ));
+        let patterns = column_names.iter().map(column_pattern);
+        quote! {
+            /// The SQL spelling of `column`, which has to be one of `COLUMNS`.
+            fn check_column(column: &str) -> Result<&'static str, sqlx::Error> {
+                match column {
+                    #(#patterns => Ok(#column_names),)*
+                    _ => Err(sqlx::Error::ColumnNotFound(column.to_string())),
+                }
+            }
+
+            /// The number of rows per distinct value of `column`, as text.
+            /// Rows where `column` is null are counted under `None`.
+            pub async fn count_by(
+                column: &str,
+                conn: &Connection,
+            ) -> Result<Vec<(Option<String>, i64)>, sqlx::Error> {
+                let column = Self::check_column(column)?;
+                let query = format!(#count_by);
+                sqlx::query_as::<_, (Option<String>, i64)>(&query)
+                    .fetch_all(conn)
+                    .await
+            }
+        }
+    };
+
+    let aggregates = {
+        let float = keywords(
+            match Dialect::current() {
+                Dialect::Sqlite => "real",
+                Dialect::Postgres => "double precision",
+                Dialect::MySql => "double",
+            }
+            .to_string(),
+        );
+        let integer = keywords(
+            match Dialect::current() {
+                Dialect::Sqlite => "integer",
+                Dialect::Postgres => "bigint",
+                Dialect::MySql => "signed",
+            }
+            .to_string(),
+        );
+        let functions = ["max", "min", "sum", "avg"];
+        let function_names = functions.map(|function| keywords(function.to_string()));
+        let query = keywords(format!("select {{function}}({{column}}) from {table};"));
+        let cast_query = keywords(format!(
+            "select cast({{function}}({{column}}) as {{sql_type}}) from {table};"
+        ));
+        let aggregate = |function: &str, doc: &str| {
+            let method = format_ident!("{function}");
+            quote! {
+                #[doc = #doc]
+                pub async fn #method<Value>(column: &str, conn: &Connection) -> Result<Option<Value>, sqlx::Error>
+                where
+                    Value: for<'r> sqlx::Decode<'r, #database> + sqlx::Type<#database> + Send + Unpin,
+                {
+                    Self::aggregate(#function, column, conn).await
+                }
+            }
+        };
+        let max = aggregate(
+            "max",
+            " The largest value of a numeric `column`, in its own type.",
+        );
+        let min = aggregate(
+            "min",
+            " The smallest value of a numeric `column`, in its own type.",
+        );
+        let sum = aggregate(
+            "sum",
+            " The total of a numeric `column`, an `i64` for integer columns and an `f64` for\n floats.",
+        );
+        quote! {
+            /// The statement computing `function` over `column`, which has to
+            /// be one of the numeric columns.
+            fn aggregate_query(function: &str, column: &str) -> Result<String, sqlx::Error> {
+                let numeric_columns: &[&str] = &[#(#numeric_columns),*];
+                let float_columns: &[&str] = &[#(#float_columns),*];
+                let column = match Self::check_column(column) {
+                    Ok(column) if numeric_columns.contains(&column) => column,
+                    _ => return Err(sqlx::Error::ColumnNotFound(column.to_string())),
+                };
+                // an integer total is widened so it can't overflow the column type
+                let sql_type = match function {
+                    "sum" if !float_columns.contains(&column) => Some(#integer),
+                    "sum" | "avg" => Some(#float),
+                    _ => None,
+                };
+                // spelled like the statement's other keywords
+                let function = match function {
+                    #(#functions => #function_names,)*
+                    _ => unreachable!(),
+                };
+                Ok(match sql_type {
+                    Some(sql_type) => format!(#cast_query),
+                    None => format!(#query),
+                })
+            }
+
+            async fn aggregate<Value>(
+                function: &str,
+                column: &str,
+                conn: &Connection,
+            ) -> Result<Option<Value>, sqlx::Error>
+            where
+                Value: for<'r> sqlx::Decode<'r, #database> + sqlx::Type<#database> + Send + Unpin,
+            {
+                let query = Self::aggregate_query(function, column)?;
+                let (value,) = sqlx::query_as::<_, (Option<Value>,)>(&query)
+                    .fetch_one(conn)
+                    .await?;
+                Ok(value)
+            }
+
+            #max
+            #min
+            #sum
+
+            /// The mean of a numeric `column`.
+            pub async fn avg(column: &str, conn: &Connection) -> Result<Option<f64>, sqlx::Error> {
+                Self::aggregate("avg", column, conn).await
+            }
+        }
+    };
+
+    // the trait lives in the runtime crate, a proc-macro crate can't export
+    // one, and has to be object safe with exactly these methods:
+    //
+    //     pub trait DynModel {
+    //         fn table_name(&self) -> &'static str;
+    //         fn columns(&self) -> &'static str;
+    //         fn create_sql(&self) -> &'static str;
+    //         fn insert_sql(&self) -> String;
+    //     }
+    let dyn_model = if cfg!(feature = "dyn-model") {
+        let insert = if create_args.is_empty() {
+            format!("insert into {table} {default_values};")
+        } else {
+            let columns = create_args
+                .iter()
+                .map(|f| identifier(&f.to_string(), lowercase_identifiers, uppercase_keywords))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let values = (1..=create_args.len())
+                .map(|index| format!("?{index}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("insert into {table} ({columns}) values ({values});")
+        };
+        let insert = keywords(insert);
+        quote! {
+            impl #impl_generics rusql_alchemy::DynModel for #name #ty_generics #where_clause {
+                fn table_name(&self) -> &'static str {
+                    <Self as Model>::NAME
+                }
+
+                fn columns(&self) -> &'static str {
+                    Self::COLUMNS
+                }
+
+                fn create_sql(&self) -> &'static str {
+                    <Self as Model>::SCHEMA
+                }
+
+                fn insert_sql(&self) -> String {
+                    let placeholder = rusql_alchemy::PLACEHOLDER.to_string();
+                    #insert.replace("?", &placeholder).replace("$", &placeholder)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let from_row = if from_row {
+        let row = match Dialect::current() {
+            Dialect::Sqlite => quote! { sqlx::sqlite::SqliteRow },
+            Dialect::Postgres => quote! { sqlx::postgres::PgRow },
+            Dialect::MySql => quote! { sqlx::mysql::MySqlRow },
+        };
+        quote! {
+            impl #impl_generics sqlx::FromRow<'_, #row> for #name #ty_generics #where_clause {
+                fn from_row(row: &#row) -> Result<Self, sqlx::Error> {
+                    use sqlx::Row;
+                    Ok(Self {
+                        #(#row_fields),*
+                    })
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let expanded = quote! {
+        #[async_trait]
+        impl #impl_generics Model for #name #ty_generics #where_clause {
+            const NAME: &'static str = #table;
+            #schema
+            #primary_key
+            #create
+            #update
+            #delete
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #primary_key_type
+            #pk_predicate
+            #placeholder_style
+            #alters
+            #diff_schema
+            #migrations
+            #depends_on
+            #columns_const
+            #columns_hash
+            #schema_version
+            #schema_in
+            #schema_for_test
+            #insert
+            #apply_defaults
+            #delete_sql
+            #hard_delete
+            #restore
+            #change_pk
+            #patch
+            #delete_cascade
+            #bulk_create
+            #bulk_create_returning
+            #defer_constraints
+            #count
+            #all
+            #find_one
+            #find_by
+            #get_or_create
+            #count_by
+            #aggregates
+            #(#related_loaders)*
+        }
+
+        #from_row
+
+        #dyn_model
+
+        #(#relation_checks)*
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Collects the `SCHEMA` of every listed model, ordered so that each table
+/// comes after the tables in its `DEPENDS_ON`.
+///
+/// Only the listed models are collected, there's no registry of every model
+/// deriving `Model`. A table a listed model depends on without being listed
+/// itself is assumed to exist already. Panics when the listed models
+/// reference each other in a cycle.
+///
+/// ```ignore
+/// for schema in all_schemas![User, Post, Comment] {
+///     sqlx::query(schema).execute(&conn).await?;
+/// }
+/// ```
+#[proc_macro]
+pub fn all_schemas(input: TokenStream) -> TokenStream {
+    let models = parse_macro_input!(
+        input with syn::punctuated::Punctuated::<syn::Path, syn::Token![,]>::parse_terminated
+    );
+    let models = models.iter();
+
+    let expanded = quote! {
+        {
+            // (name, depends on, schema)
+            let models: &[(&str, &[&str], &'static str)] = &[
+                #((<#models as Model>::NAME, #models::DEPENDS_ON, <#models as Model>::SCHEMA)),*
+            ];
+            // a name may be quoted in one model and bare in another
+            fn same_table(a: &str, b: &str) -> bool {
+                let quotes: &[char] = &['"', '`'];
+                a.trim_matches(quotes).eq_ignore_ascii_case(b.trim_matches(quotes))
+            }
+            let is_listed = |table: &str| {
+                models.iter().any(|(name, _, _)| same_table(name, table))
+            };
+            let mut created: Vec<&str> = Vec::new();
+            let mut schemas: Vec<&'static str> = Vec::new();
+            while schemas.len() < models.len() {
+                let before = schemas.len();
+                for (name, depends_on, schema) in models {
+                    let ready = depends_on.iter().all(|table| {
+                        !is_listed(table) || created.iter().any(|name| same_table(name, table))
+                    });
+                    if ready && !created.contains(name) {
+                        created.push(name);
+                        schemas.push(schema);
+                    }
+                }
+                if schemas.len() == before {
+                    let cycle = models
+                        .iter()
+                        .map(|(name, _, _)| *name)
+                        .filter(|name| !created.contains(name))
+                        .collect::<Vec<_>>();
+                    panic!("can't order the schemas of {}, their foreign keys form a cycle", cycle.join(", "));
+                }
+            }
+            schemas
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Dialect {
+    Sqlite,
+    Postgres,
+    MySql,
+}
+
+impl Dialect {
+    fn current() -> Self {
+        if cfg!(feature = "postgres") {
+            Dialect::Postgres
+        } else if cfg!(feature = "mysql") {
+            Dialect::MySql
+        } else {
+            Dialect::Sqlite
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sqlite" => Some(Dialect::Sqlite),
+            "postgres" => Some(Dialect::Postgres),
+            "mysql" => Some(Dialect::MySql),
+            _ => None,
+        }
+    }
+}
+
+/// The literal a `#[model(...)]` attribute takes.
+#[derive(Clone, Copy, PartialEq)]
+enum AttributeValue {
+    /// a boolean, `#[model(unique)]` is shorthand for `#[model(unique = true)]`
+    Flag,
+    Str,
+    Int,
+    /// any literal, `default` checks the kind against the field itself
+    Literal,
+}
+
+const STRUCT_ATTRIBUTES: &[(&str, AttributeValue)] = &[
+    ("soft_delete", AttributeValue::Str),
+    ("comment", AttributeValue::Str),
+    ("uppercase_keywords", AttributeValue::Flag),
+    ("allowed_functions", AttributeValue::Str),
+    ("require_default_for_not_null", AttributeValue::Flag),
+    ("upsert_on", AttributeValue::Str),
+    ("from_row", AttributeValue::Flag),
+    ("identifier_case", AttributeValue::Str),
+    ("cascade", AttributeValue::Str),
+    ("relation", AttributeValue::Str),
+    ("fk", AttributeValue::Str),
+];
+
+const FIELD_ATTRIBUTES: &[(&str, AttributeValue)] = &[
+    ("primary_key", AttributeValue::Flag),
+    ("auto", AttributeValue::Flag),
+    ("column_type", AttributeValue::Str),
+    ("fixed", AttributeValue::Flag),
+    ("min_size", AttributeValue::Int),
+    ("size", AttributeValue::Int),
+    ("unique", AttributeValue::Flag),
+    ("default", AttributeValue::Literal),
+    ("default_raw", AttributeValue::Str),
+    ("comment", AttributeValue::Str),
+    ("dialect", AttributeValue::Str),
+    ("deferrable", AttributeValue::Flag),
+    ("check", AttributeValue::Str),
+    ("on_update", AttributeValue::Str),
+    ("cdc", AttributeValue::Flag),
+    ("auto_now_add", AttributeValue::Flag),
+    ("immutable", AttributeValue::Flag),
+    ("default_fn", AttributeValue::Str),
+    ("default_from", AttributeValue::Str),
+    ("enum_table", AttributeValue::Str),
+    ("choices", AttributeValue::Str),
+    ("foreign_key", AttributeValue::Str),
+    ("related", AttributeValue::Str),
+    ("db_type_", AttributeValue::Str),
+];
+
+/// Checks one entry of a `#[model(...)]` list against the attributes it may
+/// name and the literal each of them takes. A bare flag comes back as
+/// `flag = true`. Names ending in `_` are prefixes, the dialect of a
+/// `db_type_*` override is checked where it's read.
+fn model_attribute(
+    nested: &syn::NestedMeta,
+    attributes: &[(&str, AttributeValue)],
+) -> Result<syn::MetaNameValue, syn::Error> {
+    let kind = |path: &syn::Path| {
+        let name = path.get_ident()?.to_string();
+        attributes
+            .iter()
+            .find(|(attribute, _)| match attribute.strip_suffix('_') {
+                Some(prefix) => name.starts_with(prefix) && name.len() > attribute.len(),
+                None => *attribute == name,
+            })
+            .map(|(_, kind)| *kind)
+    };
+    match nested {
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) => match kind(path) {
+            Some(AttributeValue::Flag) => Ok(syn::MetaNameValue {
+                path: path.clone(),
+                eq_token: Default::default(),
+                lit: Lit::Bool(syn::LitBool::new(true, path.span())),
+            }),
+            Some(_) => Err(syn::Error::new_spanned(
+                path,
+                format!("expected `{} = ...`", path.get_ident().unwrap()),
+            )),
+            None => Err(syn::Error::new_spanned(path, "unknown model attribute")),
+        },
+        syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => {
+            let expected = match (kind(&nv.path), &nv.lit) {
+                (None, _) => {
+                    return Err(syn::Error::new_spanned(&nv.path, "unknown model attribute"));
+                }
+                (Some(AttributeValue::Flag), Lit::Bool(_))
+                | (Some(AttributeValue::Str), Lit::Str(_))
+                | (Some(AttributeValue::Int), Lit::Int(_))
+                | (Some(AttributeValue::Literal), _) => return Ok(nv.clone()),
+                (Some(AttributeValue::Flag), _) => "a boolean",
+                (Some(AttributeValue::Str), _) => "a string literal",
+                (Some(AttributeValue::Int), _) => "an integer literal",
+            };
+            Err(syn::Error::new_spanned(
+                &nv.lit,
+                format!("`{}` expects {expected}", nv.path.get_ident().unwrap()),
+            ))
+        }
+        nested => Err(syn::Error::new_spanned(nested, "unknown model attribute")),
+    }
+}
+
+/// Whether a default starts with the word `now` or `current_timestamp`,
+/// followed by nothing, `()`, `+` or `-`. `nowhere` is a plain string.
+fn is_now_expr(value: &str) -> bool {
+    let value = value.trim();
+    let Some(rest) = value
+        .strip_prefix("now")
+        .or_else(|| value.strip_prefix("current_timestamp"))
+    else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    rest.is_empty() || rest.starts_with("()") || rest.starts_with(['+', '-'])
+}
+
+/// Renders `now +/- interval '<amount> <unit>'` into the current dialect's
+/// date arithmetic, e.g. `datetime('now','+7 days')` on sqlite. A bare
+/// `now()` or `current_timestamp` is the current date, time or timestamp.
+fn default_expr(value: &str, field_type: &str) -> Option<String> {
+    let value = value.trim();
+    let rest = value
+        .strip_prefix("now")
+        .or_else(|| value.strip_prefix("current_timestamp"))?
+        .trim_start();
+    let rest = rest.strip_prefix("()").unwrap_or(rest).trim_start();
+    if rest.is_empty() {
+        return Some(
+            match field_type {
+                "Date" => "current_date",
+                "Time" => "current_time",
+                _ => "current_timestamp",
+            }
+            .to_string(),
+        );
+    }
+    let sign = rest.chars().next().filter(|c| *c == '+' || *c == '-')?;
+    let interval = rest[1..]
+        .trim()
+        .strip_prefix("interval")?
+        .trim()
+        .strip_prefix('\'')?
+        .strip_suffix('\'')?;
+
+    let mut parts = interval.split_whitespace();
+    let amount = parts.next()?.parse::<u64>().ok()?;
+    let unit = parts.next()?.trim_end_matches('s');
+    if parts.next().is_some()
+        || !["second", "minute", "hour", "day", "month", "year"].contains(&unit)
+    {
+        return None;
+    }
+
+    Some(match Dialect::current() {
+        Dialect::Sqlite => {
+            let function = match field_type {
+                "Date" => "date",
+                "Time" => "time",
+                _ => "datetime",
+            };
+            format!("({function}('now','{sign}{amount} {unit}s'))")
+        }
+        Dialect::Postgres => {
+            let now = match field_type {
+                "Date" => "current_date",
+                "Time" => "current_time",
+                _ => "current_timestamp",
+            };
+            format!("({now} {sign} interval '{amount} {unit}s')")
+        }
+        Dialect::MySql => {
+            let now = match field_type {
+                "Date" => "curdate()",
+                "Time" => "curtime()",
+                _ => "now()",
+            };
+            format!("({now} {sign} interval {amount} {unit})")
+        }
+    })
+}
+
+/// Whether a rendered default is a literal rather than an expression, the
+/// only kind sqlite accepts when adding a column.
+fn is_constant_default(default: &str) -> bool {
+    let default = default.trim();
+    let number = default.trim_start_matches(['-', '+']);
+    default.starts_with('\'')
+        || default.starts_with("x'")
+        || number.starts_with(|c: char| c.is_ascii_digit())
+        || ["null", "true", "false"]
+            .iter()
+            .any(|literal| default.eq_ignore_ascii_case(literal))
+}
+
+fn needs_explicit_cast(sql_type: &str) -> bool {
+    matches!(sql_type, "json" | "jsonb" | "uuid" | "timestamptz") || sql_type.ends_with("[]")
+}
+
+/// Whether `value` is a `YYYY-MM-DD HH:MM:SS` timestamp, `T` separated or
+/// not, with optional fractional seconds and a `Z` or `+HH:MM` offset.
+fn is_timestamp(value: &str) -> bool {
+    let number = |part: &str, max: u32| {
+        part.bytes().all(|b| b.is_ascii_digit()) && part.parse::<u32>().is_ok_and(|n| n <= max)
+    };
+    let (Some(date), Some(time)) = (value.get(..10), value.get(11..)) else {
+        return false;
+    };
+    if !matches!(&value[10..11], "T" | "t" | " ") {
+        return false;
+    }
+    let date = date.split('-').collect::<Vec<_>>();
+    let [year, month, day] = date[..] else {
+        return false;
+    };
+    if year.len() != 4 || !number(year, 9999) || !number(month, 12) || !number(day, 31) {
+        return false;
+    }
+    if month == "00" || day == "00" {
+        return false;
+    }
+
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(index) => time.split_at(index),
+        None => (time, ""),
+    };
+    let time = time.split_once('.').map_or(time, |(time, fraction)| {
+        if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            ""
+        } else {
+            time
+        }
+    });
+    let time = time.split(':').collect::<Vec<_>>();
+    let [hour, minute, second] = time[..] else {
+        return false;
+    };
+    if [hour, minute, second].iter().any(|part| part.len() != 2)
+        || !number(hour, 23)
+        || !number(minute, 59)
+        || !number(second, 60)
+    {
+        return false;
+    }
+
+    match offset {
+        "" | "Z" | "z" => true,
+        offset => offset[1..].split_once(':').is_some_and(|(hours, minutes)| {
+            hours.len() == 2 && minutes.len() == 2 && number(hours, 23) && number(minutes, 59)
+        }),
+    }
+}
+
+/// Renders a `0x`-prefixed hex string as the current dialect's binary literal.
+/// Mysql only accepts a `blob` default as a parenthesized expression.
+fn binary_literal(value: &str) -> Option<String> {
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))?;
+    if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(match Dialect::current() {
+        Dialect::Postgres => format!("decode('{}', 'hex')", hex.to_lowercase()),
+        Dialect::Sqlite => format!("x'{}'", hex.to_uppercase()),
+        Dialect::MySql => format!("(x'{}')", hex.to_uppercase()),
+    })
+}
+
+/// The default of a boolean field, `true`/`false` on a postgres `boolean`
+/// column and `1`/`0` for integer storage, mysql's `tinyint(1)` included.
+fn bool_literal(value: bool, boolean_column: bool) -> proc_macro2::TokenStream {
+    match (
+        Dialect::current() == Dialect::Postgres && boolean_column,
+        value,
+    ) {
+        (true, true) => quote! { true },
+        (true, false) => quote! { false },
+        (false, true) => quote! { 1 },
+        (false, false) => quote! { 0 },
+    }
+}
+
+/// Functions `default_raw` may call when the allowlist is enabled.
+const DEFAULT_FUNCTIONS: &[&str] = &[
+    "now",
+    "current_timestamp",
+    "current_date",
+    "current_time",
+    "localtimestamp",
+    "gen_random_uuid",
+    "uuid_generate_v4",
+    "uuid",
+    "nextval",
+    "datetime",
+    "date",
+    "time",
+    "strftime",
+    "lower",
+    "upper",
+    "random",
+];
+
+/// Rejects a `default_raw` expression calling a function that isn't in
+/// `allowed_functions` or chaining statements.
+fn check_default_raw(raw: &str, allowed_functions: &[String]) -> Result<(), String> {
+    if raw.contains(';') || raw.contains("--") || raw.contains("/*") {
+        return Err("`default_raw` must be a single expression".to_string());
+    }
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            // skips string literals, `''` escapes included
+            for c in chars.by_ref() {
+                if c == '\'' {
+                    break;
+                }
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut word = c.to_string();
+            while let Some(&c) = chars
+                .peek()
+                .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+            {
+                word.push(c);
+                chars.next();
+            }
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            let word = word.to_lowercase();
+            if chars.peek() == Some(&'(') && !allowed_functions.contains(&word) {
+                return Err(format!(
+                    "`{word}` isn't an allowed `default_raw` function, add it to `allowed_functions`"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rejects empty `check` expressions and ones with unbalanced parentheses or
+/// unterminated quotes. It's no SQL parser, it only catches obvious typos.
+fn check_expression(expr: &str) -> Result<(), &'static str> {
+    if expr.trim().is_empty() {
+        return Err("`check` can't be empty");
+    }
+    let mut depth = 0usize;
+    let mut chars = expr.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // the guard skips past the closing quote
+            '\'' | '"' | '`' if !chars.by_ref().any(|close| close == c) => {
+                return Err("`check` has an unterminated quote");
+            }
+            '(' => depth += 1,
+            ')' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or("`check` has a `)` without a matching `(`")?;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err("`check` has a `(` without a matching `)`");
+    }
+    Ok(())
+}
+
+/// Whether `value` is a single well-formed JSON value, without duplicate
+/// object keys. `serde_json` alone keeps the last of those.
+fn is_json(value: &str) -> bool {
+    use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+
+    struct UniqueKeys;
+
+    impl<'de> serde::Deserialize<'de> for UniqueKeys {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(UniqueKeys)
+        }
+    }
+
+    impl<'de> Visitor<'de> for UniqueKeys {
+        type Value = UniqueKeys;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a JSON value")
+        }
+
+        fn visit_bool<E>(self, _: bool) -> Result<Self, E> {
+            Ok(self)
+        }
+
+        fn visit_i64<E>(self, _: i64) -> Result<Self, E> {
+            Ok(self)
+        }
+
+        fn visit_u64<E>(self, _: u64) -> Result<Self, E> {
+            Ok(self)
+        }
+
+        fn visit_f64<E>(self, _: f64) -> Result<Self, E> {
+            Ok(self)
+        }
+
+        fn visit_str<E>(self, _: &str) -> Result<Self, E> {
+            Ok(self)
+        }
+
+        fn visit_unit<E>(self) -> Result<Self, E> {
+            Ok(self)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self, A::Error> {
+            while seq.next_element::<UniqueKeys>()?.is_some() {}
+            Ok(self)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self, A::Error> {
+            let mut keys = Vec::new();
+            while let Some(key) = map.next_key::<String>()? {
+                if keys.contains(&key) {
+                    return Err(de::Error::custom(format!("duplicate key `{key}`")));
+                }
+                map.next_value::<UniqueKeys>()?;
+                keys.push(key);
+            }
+            Ok(self)
+        }
+    }
+
+    serde_json::from_str::<UniqueKeys>(value).is_ok()
+}
+
+/// 64-bit FNV-1a, stable across compiler versions unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn is_primary_key_field(field: &syn::Field) -> bool {
+    let mut is_primary_key = false;
+    for attr in &field.attrs {
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+            if !list.path.is_ident("model") {
+                continue;
+            }
+            for nested in &list.nested {
+                match nested {
+                    syn::NestedMeta::Meta(syn::Meta::Path(path))
+                        if path.is_ident("primary_key") =>
+                    {
+                        is_primary_key = true;
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                        if nv.path.is_ident("primary_key") =>
+                    {
+                        if let Lit::Bool(ref lit) = nv.lit {
+                            is_primary_key = lit.value;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    is_primary_key
+}
+
+const RESERVED_WORDS: &[&str] = &[
+    "all",
+    "alter",
+    "and",
+    "as",
+    "asc",
+    "between",
+    "by",
+    "case",
+    "check",
+    "column",
+    "constraint",
+    "create",
+    "default",
+    "delete",
+    "desc",
+    "distinct",
+    "drop",
+    "else",
+    "end",
+    "exists",
+    "foreign",
+    "from",
+    "grant",
+    "group",
+    "having",
+    "in",
+    "index",
+    "insert",
+    "into",
+    "is",
+    "join",
+    "key",
+    "like",
+    "limit",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "or",
+    "order",
+    "primary",
+    "references",
+    "select",
+    "set",
+    "table",
+    "then",
+    "to",
+    "union",
+    "unique",
+    "update",
+    "user",
+    "values",
+    "when",
+    "where",
+    "with",
+];
+
+/// Keywords rendered in uppercase by `uppercase_keywords`, on top of the
+/// reserved words.
+const KEYWORDS: &[&str] = &[
+    "action",
+    "add",
+    "auto_increment",
+    "autoincrement",
+    "avg",
+    "bigint",
+    "blob",
+    "boolean",
+    "bytea",
+    "cascade",
+    "cast",
+    "char",
+    "char_length",
+    "comment",
+    "conflict",
+    "constraints",
+    "count",
+    "curdate",
+    "current_date",
+    "current_time",
+    "current_timestamp",
+    "curtime",
+    "date",
+    "datetime",
+    "day",
+    "decode",
+    "deferrable",
+    "deferred",
+    "do",
+    "double",
+    "duplicate",
+    "excluded",
+    "false",
+    "float",
+    "generated",
+    "hour",
+    "identity",
+    "if",
+    "ignore",
+    "immediate",
+    "initially",
+    "integer",
+    "interval",
+    "json",
+    "jsonb",
+    "length",
+    "max",
+    "min",
+    "minute",
+    "month",
+    "no",
+    "nothing",
+    "now",
+    "precision",
+    "real",
+    "restrict",
+    "returning",
+    "second",
+    "serial",
+    "signed",
+    "sum",
+    "temporary",
+    "text",
+    "time",
+    "timestamp",
+    "tinyint",
+    "true",
+    "type",
+    "uuid",
+    "varbinary",
+    "varchar",
+    "year",
+];
+
+/// Uppercases the keywords of a generated statement. String literals, quoted
+/// identifiers and `{}` format arguments are left as is.
+fn to_uppercase_keywords(sql: &str) -> String {
+    let mut rendered = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' | '{' => {
+                let close = if c == '{' { '}' } else { c };
+                rendered.push(c);
+                for c in chars.by_ref() {
+                    rendered.push(c);
+                    if c == close {
+                        break;
+                    }
+                }
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                {
+                    word.push(c);
+                    chars.next();
+                }
+                let lowercase = word.to_lowercase();
+                if RESERVED_WORDS.contains(&lowercase.as_str())
+                    || KEYWORDS.contains(&lowercase.as_str())
+                {
+                    rendered.push_str(&word.to_uppercase());
+                } else {
+                    rendered.push_str(&word);
+                }
+            }
+            c => rendered.push(c),
+        }
+    }
+    rendered
+}
+
+/// The SQL spelling of a table or column name, reserved words are quoted the
+/// way the current dialect expects. Postgres gets them in lowercase, so
+/// `"user"` and an unquoted `User` elsewhere name the same table. With
+/// `keywords` set the names `to_uppercase_keywords` would change are quoted
+/// too.
+fn identifier(ident: &str, lowercase: bool, keywords: bool) -> String {
+    let ident = if lowercase {
+        ident.to_lowercase()
+    } else {
+        ident.to_string()
+    };
+    let lowercase = ident.to_lowercase();
+    let is_quoted = RESERVED_WORDS.contains(&lowercase.as_str())
+        || (keywords && KEYWORDS.contains(&lowercase.as_str()));
+    if !is_quoted {
+        return ident;
+    }
+    // postgres folds unquoted names to lowercase, a quoted one has to match
+    match Dialect::current() {
+        Dialect::Postgres => format!("\"{}\"", ident.to_lowercase()),
+        Dialect::Sqlite | Dialect::MySql => format!("`{ident}`"),
+    }
+}
+
+/// The `fk_{table}_{column}` name of the foreign key declared on a column.
+fn foreign_key_name(table: &str, column: &str) -> String {
+    let unquote = |ident: &str| ident.trim_matches(|c| c == '"' || c == '`').to_string();
+    format!("fk_{}_{}", unquote(table), unquote(column))
+}
+
+/// Picks the form of a statement written with `?` markers that matches
+/// `rusql_alchemy::PLACEHOLDER` where the generated code is compiled. The
+/// expression is usable in consts, and the runtime crate only ever uses `$`
+/// or `?`.
+fn placeholders(query: &str) -> proc_macro2::TokenStream {
+    let dollar = query.replace('?', "$");
+    quote! {
+        match rusql_alchemy::PLACEHOLDER.as_bytes() {
+            b"$" => #dollar,
+            _ => #query,
+        }
+    }
+}
+
+/// Renders generated SQL tokens as text. String literals spliced into the
+/// tokens are emitted as their content, so quotes inside them survive. Names
+/// have to go in as literals too, a bare `"user"` would lose its quotes.
+/// Line breaks the token printer puts into long lists become spaces.
+fn sql(tokens: &str) -> String {
+    let mut sql = String::new();
+    let mut rest = tokens;
+    while let Some(start) = rest.find('"') {
+        sql.push_str(&rest[..start].replace('\n', " "));
+        let bytes = rest.as_bytes();
+        let mut end = start + 1;
+        while bytes[end] != b'"' {
+            if bytes[end] == b'\\' {
+                end += 1;
+            }
+            end += 1;
+        }
+        let literal = syn::parse_str::<syn::LitStr>(&rest[start..=end]).unwrap();
+        sql.push_str(&literal.value());
+        rest = &rest[end + 1..];
+    }
+    sql.push_str(&rest.replace('\n', " "));
+    sql
+}
+
+fn extract_inner_type(field_type: &Type) -> String {
+    match field_type {
+        Type::Path(type_path) => {
+            let last_segment = type_path.path.segments.last().unwrap();
+            if last_segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(args) = &last_segment.arguments {
+                    if let Some(GenericArgument::Type(inner_type)) = args.args.first() {
+                        return extract_inner_type(inner_type);
+                    }
+                }
+            }
+            // `Vec<u8>` is the spelled out form of `Bytes`
+            if last_segment.ident == "Vec" {
+                if let PathArguments::AngleBracketed(args) = &last_segment.arguments {
+                    if let Some(GenericArgument::Type(Type::Path(inner_type))) = args.args.first() {
+                        if inner_type.path.is_ident("u8") {
+                            return "Bytes".to_string();
+                        }
+                    }
+                }
+            }
+            // `chrono` and `time` types map onto the crate's own aliases,
+            // `DateTime<Tz>` keeps its name whatever the time zone argument
+            match last_segment.ident.to_string().as_str() {
+                "NaiveDate" => "Date".to_string(),
+                "NaiveDateTime" | "OffsetDateTime" | "PrimitiveDateTime" => "DateTime".to_string(),
+                "NaiveTime" => "Time".to_string(),
+                other => other.to_string(),
+            }
+        }
+        _ => panic!("Unsupported field type"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn now_expressions() {
+        assert!(is_now_expr("now"));
+        assert!(is_now_expr(" now() "));
+        assert!(is_now_expr("now + interval '7 days'"));
+        assert!(is_now_expr("current_timestamp - interval '1 hour'"));
+        assert!(!is_now_expr("nowhere"));
+        assert!(!is_now_expr("now and then"));
+        assert!(!is_now_expr("current_timestamps"));
+        assert!(!is_now_expr("today"));
+    }
+
+    #[test]
+    fn default_expressions() {
+        assert_eq!(default_expr("now", "Date").as_deref(), Some("current_date"));
+        assert_eq!(
+            default_expr("now()", "Time").as_deref(),
+            Some("current_time")
+        );
+        assert_eq!(
+            default_expr("current_timestamp", "DateTime").as_deref(),
+            Some("current_timestamp")
+        );
+        let expected = match Dialect::current() {
+            Dialect::Sqlite => "(datetime('now','+7 days'))",
+            Dialect::Postgres => "(current_timestamp + interval '7 days')",
+            Dialect::MySql => "(now() + interval 7 day)",
+        };
+        assert_eq!(
+            default_expr("now + interval '7 days'", "DateTime").as_deref(),
+            Some(expected)
+        );
+        let expected = match Dialect::current() {
+            Dialect::Sqlite => "(date('now','-1 months'))",
+            Dialect::Postgres => "(current_date - interval '1 months')",
+            Dialect::MySql => "(curdate() - interval 1 month)",
+        };
+        assert_eq!(
+            default_expr("now - interval '1 month'", "Date").as_deref(),
+            Some(expected)
+        );
+        assert_eq!(default_expr("now + interval '7 weeks'", "DateTime"), None);
+        assert_eq!(default_expr("now + interval 'x days'", "DateTime"), None);
+        assert_eq!(default_expr("now * 2", "DateTime"), None);
+    }
+
+    #[test]
+    fn uppercase_keywords() {
+        assert_eq!(
+            to_uppercase_keywords("select count(*) from post where `count`=?1;"),
+            "SELECT COUNT(*) FROM post WHERE `count`=?1;"
+        );
+        assert_eq!(
+            to_uppercase_keywords("insert into t (name) values ('select {}');"),
+            "INSERT INTO t (name) VALUES ('select {}');"
+        );
+        assert_eq!(
+            to_uppercase_keywords("delete from {} where id=?1;"),
+            "DELETE FROM {} WHERE id=?1;"
+        );
+        assert_eq!(
+            to_uppercase_keywords(
+                "insert into t (a) values (?1) on conflict (a) do nothing returning id;"
+            ),
+            "INSERT INTO t (a) VALUES (?1) ON CONFLICT (a) DO NOTHING RETURNING id;"
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(identifier("Post", false, false), "Post");
+        assert_eq!(identifier("Post", true, false), "post");
+        assert_eq!(identifier("count", false, false), "count");
+        let (user, count) = match Dialect::current() {
+            Dialect::Postgres => ("\"user\"", "\"count\""),
+            Dialect::Sqlite | Dialect::MySql => ("`User`", "`count`"),
+        };
+        assert_eq!(identifier("User", false, false), user);
+        assert_eq!(identifier("count", false, true), count);
+    }
+
+    #[test]
+    fn check_expressions() {
+        assert_eq!(check_expression("age >= 18"), Ok(()));
+        assert_eq!(check_expression("name <> ')'"), Ok(()));
+        assert_eq!(check_expression(" "), Err("`check` can't be empty"));
+        assert_eq!(
+            check_expression("length(name) > 2)"),
+            Err("`check` has a `)` without a matching `(`")
+        );
+        assert_eq!(
+            check_expression("(age > 2"),
+            Err("`check` has a `(` without a matching `)`")
+        );
+        assert_eq!(
+            check_expression("name <> 'x"),
+            Err("`check` has an unterminated quote")
+        );
+    }
+
+    #[test]
+    fn default_raw_functions() {
+        let allowed = DEFAULT_FUNCTIONS
+            .iter()
+            .map(|function| function.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(check_default_raw("lower('ABC')", &allowed), Ok(()));
+        assert_eq!(check_default_raw("'pg_sleep(1)'", &allowed), Ok(()));
+        assert_eq!(
+            check_default_raw("pg_sleep (1)", &allowed),
+            Err(
+                "`pg_sleep` isn't an allowed `default_raw` function, add it to `allowed_functions`"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            check_default_raw("1; drop table users", &allowed),
+            Err("`default_raw` must be a single expression".to_string())
+        );
+        assert!(check_default_raw("1 -- comment", &allowed).is_err());
+    }
+
+    #[test]
+    fn constant_defaults() {
+        assert!(is_constant_default("'member'"));
+        assert!(is_constant_default("-1.5"));
+        assert!(is_constant_default("x'DEADBEEF'"));
+        assert!(is_constant_default("NULL"));
+        assert!(!is_constant_default("current_timestamp"));
+        assert!(!is_constant_default("(datetime('now','+7 days'))"));
+        assert!(!is_constant_default("lower('ABC')"));
+    }
+
+    #[test]
+    fn binary_literals() {
+        let expected = match Dialect::current() {
+            Dialect::Sqlite => "x'DEADBEEF'",
+            Dialect::Postgres => "decode('deadbeef', 'hex')",
+            Dialect::MySql => "(x'DEADBEEF')",
+        };
+        assert_eq!(binary_literal("0xDEADbeef").as_deref(), Some(expected));
+        assert_eq!(binary_literal("DEADBEEF"), None);
+        assert_eq!(binary_literal("0xABC"), None);
+        assert_eq!(binary_literal("0xZZ"), None);
+    }
+
+    #[test]
+    fn bool_literals() {
+        let (yes, no) = match Dialect::current() {
+            Dialect::Postgres => ("true", "false"),
+            Dialect::Sqlite | Dialect::MySql => ("1", "0"),
+        };
+        assert_eq!(bool_literal(true, true).to_string(), yes);
+        assert_eq!(bool_literal(false, true).to_string(), no);
+        assert_eq!(bool_literal(true, false).to_string(), "1");
+        assert_eq!(bool_literal(false, false).to_string(), "0");
+    }
+
+    #[test]
+    fn fnv1a_hashes() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn json_values() {
+        assert!(is_json("{}"));
+        assert!(is_json(" [1, -2.5e3, \"a\", true, null, {\"b\": []}] "));
+        assert!(is_json("\"caf\\u00e9\\n\""));
+        assert!(!is_json("{\"a\": 1, \"a\": 2}"));
+        assert!(!is_json("[1,]"));
+        assert!(!is_json("\"\\q\""));
+        assert!(!is_json("\"\\uZZZZ\""));
+        assert!(!is_json("{\"a\": 1, \"\\u0061\": 2}"));
+        assert!(!is_json("01"));
+        assert!(!is_json("{} {}"));
+        assert!(!is_json(""));
+    }
+
+    #[test]
+    fn timestamps() {
+        assert!(is_timestamp("2024-02-29 12:30:00"));
+        assert!(is_timestamp("2024-02-29T12:30:00.123Z"));
+        assert!(is_timestamp("2024-02-29T12:30:00+02:00"));
+        assert!(!is_timestamp("2024-02-29"));
+        assert!(!is_timestamp("2024-13-01 00:00:00"));
+        assert!(!is_timestamp("2024-00-10 00:00:00"));
+        assert!(!is_timestamp("2024-01-10 24:00:00"));
+        assert!(!is_timestamp("2024-01-10 10:00:00."));
+        assert!(!is_timestamp("2024-01-10 10:00:00+2:00"));
     }
 }
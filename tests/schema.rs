@@ -0,0 +1,674 @@
+mod support;
+
+use async_trait::async_trait;
+use rusql_alchemy_macro::{all_schemas, Model};
+use support::rusql_alchemy::{BigInt, Boolean, DateTime, Integer, PLACEHOLDER};
+use support::*;
+
+#[derive(Model, Clone, Default)]
+struct User {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(unique, size = 50)]
+    name: String,
+    #[model(default = "now")]
+    created: Option<DateTime>,
+    age: Option<Integer>,
+}
+
+#[derive(Model, Clone, Default)]
+struct Membership {
+    #[model(primary_key, foreign_key = "User.id")]
+    user_id: Integer,
+    #[model(primary_key)]
+    group_id: Integer,
+    #[model(default = "member")]
+    role: String,
+}
+
+#[derive(Model, Clone, Default)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(foreign_key = "User.id", deferrable)]
+    author_id: Integer,
+    #[model(foreign_key = "User.id", on_update = "cascade")]
+    editor_id: Integer,
+}
+
+#[derive(Model, Clone, Default)]
+struct Shirt {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(enum_table = "shirt_size", choices = "small, large")]
+    size: String,
+}
+
+#[derive(Model, Clone, Default)]
+#[model(soft_delete = "deleted_at")]
+struct Note {
+    #[model(primary_key, auto)]
+    id: Integer,
+    body: String,
+    deleted_at: Option<DateTime>,
+}
+
+#[derive(Model, Clone, Default)]
+#[model(uppercase_keywords)]
+struct Score {
+    #[model(primary_key, auto)]
+    id: Integer,
+    points: Integer,
+}
+
+#[derive(Model, Clone, Default)]
+struct ApiKey {
+    #[model(primary_key, default_raw = "gen_random_uuid()")]
+    id: String,
+    owner: Integer,
+}
+
+fn new_token() -> String {
+    "tok-1".to_string()
+}
+
+#[derive(Model, Clone, Default)]
+struct Invite {
+    #[model(primary_key, default_fn = "new_token")]
+    token: String,
+    email: String,
+    #[model(default_from = "email")]
+    name: String,
+}
+
+#[derive(Model, Clone, Default)]
+struct Visit {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(default = "now")]
+    at: DateTime,
+    #[model(default = 0)]
+    hits: Integer,
+}
+
+#[derive(Model, Clone, Default)]
+struct Tag {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(unique)]
+    #[model(size = 20)]
+    #[model(default = "misc")]
+    label: String,
+}
+
+#[derive(Model, Clone, Default)]
+struct Account {
+    #[model(primary_key, auto)]
+    id: BigInt,
+}
+
+#[derive(Model, Clone, Default)]
+#[model(relation = "Account", fk = "account_id")]
+struct Ledger {
+    #[model(primary_key, auto)]
+    id: Integer,
+    account_id: BigInt,
+}
+
+#[derive(Model, Clone, Default)]
+struct Entry {
+    #[model(primary_key, auto)]
+    id: Integer,
+    body: String,
+    #[model(immutable, auto_now_add)]
+    created_at: DateTime,
+}
+
+#[derive(Model, Clone, Default)]
+struct Contact {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(default = "O'Brien")]
+    surname: String,
+    #[model(default = "say \"hi\"")]
+    greeting: String,
+    #[model(default = 1_000_000)]
+    quota: Integer,
+}
+
+#[derive(Model, Clone, Default)]
+#[model(from_row)]
+struct Setting {
+    #[model(primary_key, auto)]
+    id: Integer,
+    enabled: Boolean,
+    archived: Option<Boolean>,
+}
+
+#[test]
+fn columns_follow_field_order() {
+    assert_eq!(User::COLUMNS, "id, name, created, age");
+    assert_eq!(Membership::COLUMNS, "user_id, group_id, role");
+}
+
+#[test]
+fn parents_are_created_first() {
+    assert_eq!(
+        all_schemas![Membership, User],
+        [User::SCHEMA, Membership::SCHEMA]
+    );
+}
+
+#[test]
+fn attributes_can_be_split() {
+    assert!(Tag::SCHEMA.contains("label varchar(20) unique default 'misc' not null"));
+}
+
+#[test]
+fn relation_keys_share_the_parent_key_type() {
+    // sqlite integers are 64 bit already
+    let key_type = if cfg!(any(feature = "postgres", feature = "mysql")) {
+        "bigint"
+    } else {
+        "integer"
+    };
+    assert_eq!(Account::PK_TYPE, key_type);
+    assert!(Ledger::SCHEMA.contains(&format!("account_id {key_type} not null")));
+    assert!(Ledger::SCHEMA.contains("references Account"));
+}
+
+#[test]
+fn string_defaults_keep_their_quotes() {
+    assert!(Contact::SCHEMA.contains("surname varchar(255) default 'O''Brien' not null"));
+    assert!(Contact::SCHEMA.contains(r#"greeting varchar(255) default 'say "hi"' not null"#));
+}
+
+#[test]
+fn integer_defaults_drop_their_underscores() {
+    assert!(Contact::SCHEMA.contains("quota integer default 1000000 not null"));
+}
+
+#[test]
+fn booleans_are_loaded_from_rows() {
+    use sqlx::FromRow;
+
+    // sqlite stores booleans as integers
+    let row = if cfg!(any(feature = "postgres", feature = "mysql")) {
+        sqlx::StubRow::default()
+            .with("enabled", true)
+            .with("archived", Some(false))
+    } else {
+        sqlx::StubRow::default()
+            .with("enabled", 1_i32)
+            .with("archived", Some(0_i32))
+    };
+    let setting = Setting::from_row(&row.with("id", 3)).unwrap();
+    assert_eq!(
+        (setting.id, setting.enabled, setting.archived),
+        (3, true, Some(false))
+    );
+}
+
+#[test]
+fn key_predicates() {
+    assert_eq!(User::pk_predicate(), format!("id = {PLACEHOLDER}1"));
+    assert_eq!(
+        Membership::pk_predicate(),
+        format!("user_id = {PLACEHOLDER}1 and group_id = {PLACEHOLDER}2")
+    );
+}
+
+#[test]
+fn get_or_create_looks_up_unique_columns() {
+    let conn = Connection::default();
+    conn.push_row(User {
+        id: 4,
+        name: "ann".to_string(),
+        ..Default::default()
+    });
+    let user = User {
+        name: "ann".to_string(),
+        age: Some(30),
+        ..Default::default()
+    };
+    assert_eq!(block_on(user.get_or_create(&conn)).unwrap().id, 4);
+    let table = if cfg!(feature = "postgres") {
+        "\"user\""
+    } else {
+        "`User`"
+    };
+    assert_eq!(
+        conn.executed(),
+        [Statement {
+            sql: format!(
+                "select id, name, created, age from {table} where name = {PLACEHOLDER}1 limit 1;"
+            ),
+            binds: vec!["\"ann\"".to_string()],
+        }]
+    );
+}
+
+#[test]
+fn get_or_create_looks_up_inserted_columns_without_unique_ones() {
+    let conn = Connection::default();
+    let membership = Membership {
+        user_id: 1,
+        group_id: 2,
+        ..Default::default()
+    };
+    // nothing found either time, the insert error is passed on
+    conn.push_failure("database is locked");
+    assert!(matches!(
+        block_on(membership.get_or_create(&conn)),
+        Err(sqlx::Error::Database(message)) if message == "database is locked"
+    ));
+    let lookup = Statement {
+        sql: format!(
+            "select user_id, group_id, role from Membership \
+             where user_id = {PLACEHOLDER}1 and group_id = {PLACEHOLDER}2 limit 1;"
+        ),
+        binds: vec!["1".to_string(), "2".to_string()],
+    };
+    let sql = conn
+        .executed()
+        .into_iter()
+        .map(|statement| statement.sql)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sql,
+        [
+            lookup.sql.clone(),
+            format!(
+                "insert into Membership (user_id, group_id) \
+                 values ({PLACEHOLDER}1, {PLACEHOLDER}2);"
+            ),
+            lookup.sql,
+        ]
+    );
+}
+
+#[test]
+fn get_or_create_reads_back_a_row_inserted_concurrently() {
+    let conn = Connection::default();
+    // the insert loses the race on the unique name, the second lookup
+    // finds the winner's row
+    conn.push_failure("UNIQUE constraint failed: User.name");
+    conn.push_row(User {
+        id: 9,
+        name: "bob".to_string(),
+        ..Default::default()
+    });
+    let user = User {
+        name: "bob".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(block_on(user.get_or_create(&conn)).unwrap().id, 9);
+    assert_eq!(conn.executed().len(), 3);
+}
+
+#[cfg(not(feature = "mysql"))]
+#[test]
+fn generated_keys_are_written_back_for_delete() {
+    let conn = Connection::default();
+    conn.push_row(vec![(5,)]);
+    let mut user = User {
+        name: "cy".to_string(),
+        ..Default::default()
+    };
+    block_on(user.save_returning_pk(&conn)).unwrap();
+    assert_eq!(user.id, 5);
+    assert!(block_on(user.delete(&conn)));
+    let table = if cfg!(feature = "postgres") {
+        "\"user\""
+    } else {
+        "`User`"
+    };
+    assert_eq!(
+        conn.executed(),
+        [
+            Statement {
+                sql: format!(
+                    "insert into {table} (name, age) values ({PLACEHOLDER}1, {PLACEHOLDER}2) \
+                     returning id;"
+                ),
+                binds: vec!["\"cy\"".to_string(), "None".to_string()],
+            },
+            Statement {
+                sql: format!("delete from {table} where id={PLACEHOLDER}1;"),
+                binds: vec!["5".to_string()],
+            },
+        ]
+    );
+}
+
+#[cfg(not(feature = "mysql"))]
+#[test]
+fn bulk_create_returning_returns_a_key_per_row() {
+    let conn = Connection::default();
+    conn.push_row(vec![(1,), (2,), (3,)]);
+    let users = ["a", "b", "c"].map(|name| User {
+        name: name.to_string(),
+        ..Default::default()
+    });
+    let ids = block_on(User::bulk_create_returning(&users, &conn)).unwrap();
+    assert_eq!(ids, [1, 2, 3]);
+    let executed = conn.executed();
+    assert_eq!(executed.len(), 1);
+    assert_eq!(executed[0].binds.len(), 6);
+    assert!(executed[0].sql.ends_with(&format!(
+        "values ({PLACEHOLDER}1, {PLACEHOLDER}2), ({PLACEHOLDER}3, {PLACEHOLDER}4), \
+         ({PLACEHOLDER}5, {PLACEHOLDER}6) returning id;"
+    )));
+}
+
+#[test]
+fn defaulted_keys_are_left_to_the_database() {
+    assert!(ApiKey::SCHEMA.contains(" primary key default gen_random_uuid() not null"));
+    let conn = Connection::default();
+    let key = ApiKey {
+        owner: 7,
+        ..Default::default()
+    };
+    assert!(block_on(key.save(&conn)));
+    assert_eq!(
+        conn.executed(),
+        [Statement {
+            sql: format!("insert into ApiKey (owner) values ({PLACEHOLDER}1);"),
+            binds: vec!["7".to_string()],
+        }]
+    );
+}
+
+#[test]
+fn client_defaults_are_written_back() {
+    let conn = Connection::default();
+    let mut invite = Invite {
+        email: "ann@example.com".to_string(),
+        ..Default::default()
+    };
+    assert!(block_on(invite.save(&conn)));
+    assert_eq!(
+        conn.executed()[0].binds,
+        ["\"tok-1\"", "\"ann@example.com\"", "\"ann@example.com\""]
+    );
+    // `save` fills the insert only, the struct still holds the nil key
+    assert_eq!(invite.token, "");
+
+    invite.apply_defaults();
+    assert_eq!(invite.token, "tok-1");
+    assert_eq!(invite.name, "ann@example.com");
+    assert!(block_on(invite.delete(&conn)));
+    assert_eq!(conn.executed()[0].binds, ["\"tok-1\""]);
+}
+
+#[cfg(not(feature = "mysql"))]
+#[test]
+fn defaulted_keys_are_read_back() {
+    let conn = Connection::default();
+    let mut key = ApiKey {
+        owner: 7,
+        ..Default::default()
+    };
+    conn.push_row(vec![("a0eebc99".to_string(),)]);
+    block_on(key.save_returning_pk(&conn)).unwrap();
+    assert_eq!(key.id, "a0eebc99");
+    assert_eq!(
+        conn.executed()[0].sql,
+        format!("insert into ApiKey (owner) values ({PLACEHOLDER}1) returning id;")
+    );
+}
+
+#[test]
+fn deferrable_keys() {
+    let is_mysql = cfg!(all(feature = "mysql", not(feature = "postgres")));
+    assert_eq!(
+        Post::SCHEMA.contains(" deferrable initially immediate"),
+        !is_mysql
+    );
+}
+
+#[test]
+fn lookup_tables_come_before_the_schema() {
+    assert_eq!(
+        Shirt::LOOKUP_TABLES[0],
+        "create table if not exists shirt_size (value varchar(255) primary key);"
+    );
+    assert_eq!(Shirt::LOOKUP_TABLES.len(), 3);
+    assert!(Shirt::MIGRATIONS.is_empty());
+    let key = if cfg!(all(feature = "mysql", not(feature = "postgres"))) {
+        "constraint fk_Shirt_size foreign key (size) references shirt_size (value)"
+    } else {
+        "size varchar(255) not null constraint fk_Shirt_size references shirt_size (value)"
+    };
+    assert!(Shirt::SCHEMA.contains(key), "{}", Shirt::SCHEMA);
+}
+
+#[test]
+fn creation_stamps_are_left_alone() {
+    assert!(Entry::SCHEMA.contains("created_at varchar(40) default current_timestamp not null"));
+    assert_eq!(Entry::UPDATE_COLUMNS, ["body"]);
+    let conn = Connection::default();
+    let entry = Entry {
+        id: 2,
+        body: "edited".to_string(),
+        created_at: "2024-01-01 00:00:00".to_string(),
+    };
+    assert!(block_on(entry.save(&conn)));
+    assert!(block_on(entry.update(&conn)));
+    assert_eq!(entry.created_at, "2024-01-01 00:00:00");
+    let sql = conn
+        .executed()
+        .into_iter()
+        .map(|statement| statement.sql)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sql,
+        [
+            format!("insert into Entry (body) values ({PLACEHOLDER}1);"),
+            format!("update Entry set body={PLACEHOLDER}1 where id={PLACEHOLDER}2;"),
+        ]
+    );
+}
+
+#[test]
+fn soft_delete_stamps_are_left_to_delete() {
+    assert_eq!(Note::UPDATE_COLUMNS, ["body"]);
+    let conn = Connection::default();
+    let note = Note {
+        id: 3,
+        deleted_at: Some("2024-01-01 00:00:00".to_string()),
+        ..Default::default()
+    };
+    assert!(block_on(note.update(&conn)));
+    assert!(block_on(note.delete(&conn)));
+    let sql = conn
+        .executed()
+        .into_iter()
+        .map(|statement| statement.sql)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sql,
+        [
+            format!("update Note set body={PLACEHOLDER}1 where id={PLACEHOLDER}2;"),
+            format!("update Note set deleted_at = current_timestamp where id={PLACEHOLDER}1;"),
+        ]
+    );
+}
+
+#[test]
+fn aggregates_follow_keyword_casing() {
+    let conn = Connection::default();
+    conn.push_row((Some(12_i64),));
+    conn.push_row((None::<i32>,));
+    assert_eq!(
+        block_on(Score::sum::<i64>("points", &conn)).unwrap(),
+        Some(12)
+    );
+    assert_eq!(block_on(Score::max::<i32>("points", &conn)).unwrap(), None);
+    let total_type = if cfg!(feature = "postgres") {
+        "BIGINT"
+    } else if cfg!(feature = "mysql") {
+        "SIGNED"
+    } else {
+        "INTEGER"
+    };
+    let sql = conn
+        .executed()
+        .into_iter()
+        .map(|statement| statement.sql)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sql,
+        [
+            format!("SELECT CAST(SUM(points) AS {total_type}) FROM Score;"),
+            "SELECT MAX(points) FROM Score;".to_string(),
+        ]
+    );
+}
+
+#[cfg(not(any(feature = "postgres", feature = "mysql")))]
+#[test]
+fn sqlite_statements() {
+    assert_eq!(
+        User::SCHEMA,
+        "create table if not exists `User` (id integer primary key autoincrement not null, \
+         name varchar(50) unique not null, created varchar(40) default current_timestamp, \
+         age integer);"
+    );
+    assert_eq!(
+        Membership::SCHEMA,
+        "create table if not exists Membership (user_id integer not null \
+         constraint fk_Membership_user_id references `User` (id), group_id integer not null, \
+         role varchar(255) default 'member' not null, primary key (user_id, group_id));"
+    );
+    assert!(Post::SCHEMA.contains(
+        "editor_id integer not null constraint fk_Post_editor_id references `User` (id) \
+         on update cascade"
+    ));
+    assert_eq!(User::DELETE_SQL, "delete from `User` where id=?1;");
+    assert_eq!(
+        Membership::DELETE_SQL,
+        "delete from Membership where user_id=?1 and group_id=?2;"
+    );
+}
+
+#[cfg(not(any(feature = "postgres", feature = "mysql")))]
+#[test]
+fn sqlite_alters_drop_expression_defaults() {
+    assert_eq!(
+        Visit::ALTERS,
+        [
+            "alter table Visit add column at varchar(40);",
+            "alter table Visit add column hits integer default 0 not null;",
+        ]
+    );
+}
+
+#[cfg(feature = "postgres")]
+#[test]
+fn postgres_statements() {
+    assert_eq!(
+        User::SCHEMA,
+        "create table if not exists \"user\" (id integer primary key \
+         generated by default as identity not null, name varchar(50) unique not null, \
+         created varchar(40) default current_timestamp, age integer);"
+    );
+    assert_eq!(
+        Membership::SCHEMA,
+        "create table if not exists Membership (user_id integer not null \
+         constraint fk_Membership_user_id references \"user\" (id), group_id integer not null, \
+         role varchar(255) default 'member' not null, primary key (user_id, group_id));"
+    );
+    assert!(Post::SCHEMA.contains(
+        "editor_id integer not null constraint fk_Post_editor_id references \"user\" (id) \
+         on update cascade"
+    ));
+    assert_eq!(User::DELETE_SQL, "delete from \"user\" where id=$1;");
+    assert_eq!(
+        Membership::DELETE_SQL,
+        "delete from Membership where user_id=$1 and group_id=$2;"
+    );
+}
+
+#[cfg(all(feature = "mysql", not(feature = "postgres")))]
+#[test]
+fn mysql_statements() {
+    assert_eq!(
+        User::SCHEMA,
+        "create table if not exists `User` (id integer primary key auto_increment not null, \
+         name varchar(50) unique not null, created varchar(40) default current_timestamp, \
+         age integer);"
+    );
+    // mysql ignores inline references, keys are table constraints
+    assert_eq!(
+        Membership::SCHEMA,
+        "create table if not exists Membership (user_id integer not null, \
+         group_id integer not null, role varchar(255) default 'member' not null, \
+         primary key (user_id, group_id), \
+         constraint fk_Membership_user_id foreign key (user_id) references `User` (id));"
+    );
+    assert!(Post::SCHEMA.ends_with(
+        "constraint fk_Post_editor_id foreign key (editor_id) references `User` (id) \
+         on update cascade);"
+    ));
+    assert_eq!(User::DELETE_SQL, "delete from `User` where id=?1;");
+    assert_eq!(
+        Membership::DELETE_SQL,
+        "delete from Membership where user_id=?1 and group_id=?2;"
+    );
+}
+
+#[cfg(feature = "postgres")]
+#[test]
+fn tables_are_created_per_tenant_schema() {
+    for tenant in ["tenant1", "tenant_2"] {
+        let schema = User::create_table_sql_with_schema(tenant).unwrap();
+        assert!(schema.starts_with(&format!("create table if not exists {tenant}.\"user\" (")));
+        assert_eq!(
+            schema,
+            User::SCHEMA.replace("exists \"user\"", &format!("exists {tenant}.\"user\""))
+        );
+    }
+    assert_eq!(User::create_table_sql_with_schema("tenant; drop"), None);
+    assert_eq!(User::create_table_sql_with_schema("1tenant"), None);
+    assert_eq!(User::create_table_sql_with_schema(""), None);
+}
+
+#[cfg(feature = "dyn-model")]
+#[test]
+fn models_behind_a_trait_object() {
+    use support::rusql_alchemy::DynModel;
+
+    let models: Vec<Box<dyn DynModel>> =
+        vec![Box::new(User::default()), Box::new(Membership::default())];
+    let tables = models
+        .iter()
+        .map(|model| model.table_name())
+        .collect::<Vec<_>>();
+    assert_eq!(tables, [<User as Model>::NAME, <Membership as Model>::NAME]);
+    assert_eq!(models[0].columns(), User::COLUMNS);
+    assert_eq!(models[1].create_sql(), Membership::SCHEMA);
+    // `role` is left to its ddl default
+    let placeholder = support::rusql_alchemy::PLACEHOLDER;
+    assert_eq!(
+        models[1].insert_sql(),
+        format!(
+            "insert into Membership (user_id, group_id) values ({placeholder}1, {placeholder}2);"
+        )
+    );
+}
+
+#[test]
+fn live_tables_are_diffed_against_the_model() {
+    let live = "create table Visit (hits text not null, legacy integer)";
+    let mut expected = vec![Visit::ALTERS[0].to_string()];
+    if cfg!(feature = "postgres") {
+        expected.push("alter table Visit alter column hits type integer;".to_string());
+    }
+    expected.push("alter table Visit drop column legacy;".to_string());
+    assert_eq!(Visit::diff_schema(live), expected);
+    assert_eq!(
+        Visit::diff_schema("create table Visit (id integer, at varchar(40), hits integer)"),
+        Vec::<String>::new()
+    );
+}
@@ -0,0 +1,314 @@
+//! A stand-in for the parts of `rusql_alchemy` and `sqlx` the generated code
+//! refers to. Nothing reaches a database, a `Connection` records the
+//! statements run on it and answers fetches with the rows queued on it.
+#![allow(dead_code)]
+
+use std::any::{type_name, Any};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+pub mod rusql_alchemy {
+    pub const PLACEHOLDER: &str = if cfg!(feature = "postgres") { "$" } else { "?" };
+
+    pub type Integer = i32;
+    pub type BigInt = i64;
+    pub type Serial = i32;
+    pub type Float = f64;
+    pub type Text = String;
+    pub type Boolean = bool;
+    pub type Date = String;
+    pub type DateTime = String;
+    pub type Time = String;
+    pub type Bytes = Vec<u8>;
+    pub type Json = String;
+
+    pub trait DynModel {
+        fn table_name(&self) -> &'static str;
+        fn columns(&self) -> &'static str;
+        fn create_sql(&self) -> &'static str;
+        fn insert_sql(&self) -> String;
+    }
+}
+
+pub mod sqlx {
+    use std::fmt::Debug;
+    use std::marker::PhantomData;
+
+    #[derive(Debug)]
+    pub enum Error {
+        ColumnNotFound(String),
+        RowNotFound,
+        ColumnDecode(String),
+        Database(String),
+    }
+
+    pub struct Sqlite;
+    pub struct Postgres;
+    pub struct MySql;
+
+    pub trait Encode<'q, DB>: Debug {}
+    impl<'q, DB, T: Debug> Encode<'q, DB> for T {}
+    pub trait Decode<'r, DB> {}
+    impl<'r, DB, T> Decode<'r, DB> for T {}
+    pub trait Type<DB> {}
+    impl<DB, T> Type<DB> for T {}
+
+    #[cfg(not(any(feature = "postgres", feature = "mysql")))]
+    pub type Database = Sqlite;
+    #[cfg(feature = "postgres")]
+    pub type Database = Postgres;
+    #[cfg(all(feature = "mysql", not(feature = "postgres")))]
+    pub type Database = MySql;
+
+    pub trait Executor<'e>: Send {
+        type Database;
+
+        fn connection(self) -> &'e super::Connection;
+    }
+    impl<'e> Executor<'e> for &'e super::Connection {
+        type Database = Database;
+
+        fn connection(self) -> &'e super::Connection {
+            self
+        }
+    }
+    impl<'e> Executor<'e> for &'e mut super::Connection {
+        type Database = Database;
+
+        fn connection(self) -> &'e super::Connection {
+            self
+        }
+    }
+
+    pub trait FromRow<'r, R>: Sized {
+        fn from_row(row: &'r R) -> Result<Self, Error>;
+    }
+
+    pub trait Row {
+        fn try_get<T: Clone + 'static, I: AsRef<str>>(&self, index: I) -> Result<T, Error>;
+    }
+
+    /// A row of named values, each read back as the type it was stored as.
+    #[derive(Default)]
+    pub struct StubRow(Vec<(&'static str, Box<dyn std::any::Any + Send>)>);
+
+    impl StubRow {
+        pub fn with<T: Send + 'static>(mut self, column: &'static str, value: T) -> Self {
+            self.0.push((column, Box::new(value)));
+            self
+        }
+    }
+
+    impl Row for StubRow {
+        fn try_get<T: Clone + 'static, I: AsRef<str>>(&self, index: I) -> Result<T, Error> {
+            let index = index.as_ref();
+            let (_, value) = self
+                .0
+                .iter()
+                .find(|(column, _)| *column == index)
+                .ok_or_else(|| Error::ColumnNotFound(index.to_string()))?;
+            value
+                .downcast_ref::<T>()
+                .cloned()
+                .ok_or_else(|| Error::ColumnDecode(index.to_string()))
+        }
+    }
+
+    pub mod sqlite {
+        pub type SqliteRow = super::StubRow;
+    }
+
+    pub mod postgres {
+        pub type PgRow = super::StubRow;
+    }
+
+    pub mod mysql {
+        pub type MySqlRow = super::StubRow;
+    }
+
+    pub struct Query {
+        pub sql: String,
+        pub binds: Vec<String>,
+    }
+
+    pub fn query(sql: &str) -> Query {
+        Query {
+            sql: sql.to_string(),
+            binds: Vec::new(),
+        }
+    }
+
+    impl Query {
+        pub fn bind<T: Debug>(mut self, value: T) -> Self {
+            self.binds.push(format!("{value:?}"));
+            self
+        }
+
+        pub async fn execute<'e, E: Executor<'e>>(self, executor: E) -> Result<(), Error> {
+            let conn = executor.connection();
+            conn.record(self.sql, self.binds);
+            match conn.next_row::<super::Failure>() {
+                Some(super::Failure(message)) => Err(Error::Database(message)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    pub struct QueryAs<DB, O> {
+        pub sql: String,
+        pub binds: Vec<String>,
+        row: PhantomData<fn() -> (DB, O)>,
+    }
+
+    pub fn query_as<DB, O>(sql: &str) -> QueryAs<DB, O> {
+        QueryAs {
+            sql: sql.to_string(),
+            binds: Vec::new(),
+            row: PhantomData,
+        }
+    }
+
+    impl<DB, O> QueryAs<DB, O> {
+        pub fn bind<T: Debug>(mut self, value: T) -> Self {
+            self.binds.push(format!("{value:?}"));
+            self
+        }
+
+        pub async fn fetch_all<'e, E: Executor<'e, Database = DB>>(
+            self,
+            executor: E,
+        ) -> Result<Vec<O>, Error> {
+            let conn = executor.connection();
+            conn.record(self.sql, self.binds);
+            Ok(conn.next_row::<Vec<O>>().unwrap_or_default())
+        }
+
+        pub async fn fetch_one<'e, E: Executor<'e, Database = DB>>(
+            self,
+            executor: E,
+        ) -> Result<O, Error> {
+            let conn = executor.connection();
+            conn.record(self.sql, self.binds);
+            conn.next_row::<O>().ok_or(Error::RowNotFound)
+        }
+
+        pub async fn fetch_optional<'e, E: Executor<'e, Database = DB>>(
+            self,
+            executor: E,
+        ) -> Result<Option<O>, Error> {
+            let conn = executor.connection();
+            conn.record(self.sql, self.binds);
+            Ok(conn.next_row::<O>())
+        }
+    }
+}
+
+/// A statement run on a `Connection`, binds in their `Debug` form.
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub sql: String,
+    pub binds: Vec<String>,
+}
+
+/// Queued in place of a row, fails the next `execute`.
+pub struct Failure(pub String);
+
+#[derive(Default)]
+pub struct Connection {
+    statements: Mutex<Vec<Statement>>,
+    rows: Mutex<VecDeque<(&'static str, Box<dyn Any + Send>)>>,
+}
+
+impl Connection {
+    /// Queues `row` for the next fetch expecting its type, a `Vec` for
+    /// `fetch_all`.
+    pub fn push_row<T: Send + 'static>(&self, row: T) {
+        self.rows
+            .lock()
+            .unwrap()
+            .push_back((type_name::<T>(), Box::new(row)));
+    }
+
+    /// Makes the next `execute` fail.
+    pub fn push_failure(&self, message: &str) {
+        self.push_row(Failure(message.to_string()));
+    }
+
+    /// The statements run so far, transactions included once committed.
+    pub fn executed(&self) -> Vec<Statement> {
+        std::mem::take(&mut *self.statements.lock().unwrap())
+    }
+
+    fn record(&self, sql: String, binds: Vec<String>) {
+        self.statements
+            .lock()
+            .unwrap()
+            .push(Statement { sql, binds });
+    }
+
+    /// Takes the first queued row if it is a `T`, leaving other rows queued.
+    /// Rows are told apart by type name, the generated code fetches types
+    /// that aren't `'static` as far as the signatures go.
+    fn next_row<T>(&self) -> Option<T> {
+        let mut rows = self.rows.lock().unwrap();
+        if rows.front()?.0 != type_name::<T>() {
+            return None;
+        }
+        let (_, row) = rows.pop_front()?;
+        // SAFETY: the row was queued as a `T`, the names match
+        Some(unsafe { *Box::from_raw(Box::into_raw(row) as *mut T) })
+    }
+
+    pub async fn begin(&self) -> Result<Transaction<'_>, sqlx::Error> {
+        Ok(Transaction(self, Connection::default()))
+    }
+}
+
+pub struct Transaction<'c>(&'c Connection, Connection);
+
+impl Transaction<'_> {
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        let statements = self.1.executed();
+        self.0.statements.lock().unwrap().extend(statements);
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for Transaction<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.1
+    }
+}
+
+impl std::ops::DerefMut for Transaction<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.1
+    }
+}
+
+/// Runs `future` to completion, nothing in here ever waits.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut context = Context::from_waker(Waker::noop());
+    match pin!(future).poll(&mut context) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("the stub connection never waits"),
+    }
+}
+
+pub type Kwargs = Vec<(String, String)>;
+
+#[async_trait::async_trait]
+pub trait Model {
+    const NAME: &'static str;
+    const SCHEMA: &'static str;
+    const PK: &'static str;
+
+    async fn save(&self, conn: &Connection) -> bool;
+    async fn update(&self, conn: &Connection) -> bool;
+    async fn delete(&self, conn: &Connection) -> bool;
+}
@@ -0,0 +1,13 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(primary_key)]
+    version: Integer,
+}
+
+fn main() {}
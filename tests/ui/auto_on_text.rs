@@ -0,0 +1,11 @@
+use rusql_alchemy_macro::Model;
+
+type Text = String;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto)]
+    code: Text,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+type Text = String;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(default = true)]
+    flag: Text,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+type Bytes = Vec<u8>;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(default = "DEADBEEF")]
+    magic: Bytes,
+}
+
+fn main() {}
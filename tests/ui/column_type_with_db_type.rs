@@ -0,0 +1,13 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(column_type = "bigint", db_type_postgres = "int8")]
+    count: Integer,
+}
+
+fn main() {}
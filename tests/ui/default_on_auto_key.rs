@@ -0,0 +1,11 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto, default = 1)]
+    id: Integer,
+}
+
+fn main() {}
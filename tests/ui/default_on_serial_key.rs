@@ -0,0 +1,11 @@
+use rusql_alchemy_macro::Model;
+
+type Serial = i32;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, default_raw = "1")]
+    id: Serial,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+#[model(allowed_functions = "")]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(default_raw = "pg_sleep(1)")]
+    delay: Integer,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+type Json = String;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(default = "{\"a\": 1, \"a\": 2}")]
+    settings: Json,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use rusql_alchemy_macro::Model;
+
+type DateTime = String;
+
+#[derive(Model)]
+struct Visit {
+    path: String,
+    #[model(default = "now")]
+    at: Option<DateTime>,
+}
+
+fn main() {}
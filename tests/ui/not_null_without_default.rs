@@ -0,0 +1,14 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+#[model(require_default_for_not_null)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(default_raw = "null")]
+    title: String,
+}
+
+fn main() {}
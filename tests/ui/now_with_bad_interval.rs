@@ -0,0 +1,14 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+type DateTime = String;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(default = "now + interval '7 weeks'")]
+    expires: DateTime,
+}
+
+fn main() {}
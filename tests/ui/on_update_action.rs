@@ -0,0 +1,13 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    #[model(foreign_key = "user.id", on_update = "explode")]
+    user_id: Integer,
+}
+
+fn main() {}
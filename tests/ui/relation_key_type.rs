@@ -0,0 +1,23 @@
+#[path = "../support/mod.rs"]
+mod support;
+
+use async_trait::async_trait;
+use rusql_alchemy_macro::Model;
+use support::rusql_alchemy::{BigInt, Integer};
+use support::*;
+
+#[derive(Model, Clone, Default)]
+struct Account {
+    #[model(primary_key, auto)]
+    id: BigInt,
+}
+
+#[derive(Model, Clone, Default)]
+#[model(relation = "Account", fk = "account_id")]
+struct Ledger {
+    #[model(primary_key, auto)]
+    id: Integer,
+    account_id: String,
+}
+
+fn main() {}
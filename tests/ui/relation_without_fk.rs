@@ -0,0 +1,12 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+#[model(relation = "User")]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+}
+
+fn main() {}
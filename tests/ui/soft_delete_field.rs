@@ -0,0 +1,13 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+#[model(soft_delete = "deleted")]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    deleted: Integer,
+}
+
+fn main() {}
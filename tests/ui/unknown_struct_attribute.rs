@@ -0,0 +1,12 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+#[model(soft_delete_at = "deleted_at")]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+}
+
+fn main() {}
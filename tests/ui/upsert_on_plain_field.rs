@@ -0,0 +1,13 @@
+use rusql_alchemy_macro::Model;
+
+type Integer = i32;
+
+#[derive(Model)]
+#[model(upsert_on = "name")]
+struct Post {
+    #[model(primary_key, auto)]
+    id: Integer,
+    name: String,
+}
+
+fn main() {}